@@ -0,0 +1,139 @@
+use std::env;
+use std::collections::HashMap;
+use serde::Deserialize;
+use rsdiary_common::config::{load_config_file, take_options};
+
+// 設定ファイルの値は、環境変数、コマンドラインの順に上書きされる。
+// 項目     設定ファイル   環境変数       オプション
+// 索引     index_dir      INDEX_DIR      --index-dir
+// 辞書     res_dir        RES_DIR        --res-dir
+// 文書     roots          -              -
+// 日付     date_pattern   DATE_PATTERN   --date-pattern
+// 形式     [formats]      -              -              (拡張子 = "markdown" など)
+// 分割     split_by_heading -            -              (Org と Markdown を日付の見出しで分ける)
+// 文字コード [encodings]    -              -              (ディレクトリ = "euc-jp" など。無ければ推測)
+// 変換     [filters]      -              -              (拡張子 = "pdftotext {path} -" など)
+// 変換の時間 filter_timeout -            -              (秒。既定は 60)
+// メール索引 mail_index_dir MAIL_INDEX_DIR --mail-index-dir (--mail の時だけ使う)
+// メール   mailboxes      -              -              (mbox のファイルか Maildir)
+// rsdiary_search と同じファイルを読むので、知らない項目は無視する。
+
+const OPTIONS: [&str; 5] = ["config", "index-dir", "res-dir", "date-pattern", "mail-index-dir"];
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ConfigFile {
+    index_dir: Option<String>,
+    res_dir: Option<String>,
+    roots: Vec<String>,
+    date_pattern: Option<String>,
+    formats: HashMap<String, String>,
+    split_by_heading: bool,
+    encodings: HashMap<String, String>,
+    filters: HashMap<String, String>,
+    filter_timeout: Option<u64>,
+    mail_index_dir: Option<String>,
+    mailboxes: Vec<String>,
+}
+
+pub struct Config {
+    pub index_dir: String,
+    pub res_dir: String,
+    pub roots: Vec<String>,
+    pub date_pattern: Option<String>,
+    pub formats: HashMap<String, String>,
+    pub split_by_heading: bool,
+    pub encodings: HashMap<String, String>,
+    pub filters: HashMap<String, String>,
+    pub filter_timeout: u64,
+    pub mail_index_dir: Option<String>,
+    pub mailboxes: Vec<String>,
+}
+
+impl Config {
+    // args から設定用のオプションを取り除き、残りはそのまま返す。
+    pub fn load(args: &mut Vec<String>) -> Result<Config, String> {
+	let opts = take_options(args, &OPTIONS, &[])?;
+	let file: ConfigFile = load_config_file(&opts)?;
+	Self::resolve(file, &opts, |key| env::var(key).ok())
+    }
+
+    fn resolve<F>(file: ConfigFile, opts: &HashMap<String, String>, getenv: F) -> Result<Config, String>
+    where F: Fn(&str) -> Option<String> {
+	let pick = |opt: &str, var: &str, value: Option<String>| {
+	    opts.get(opt).cloned().or_else(|| getenv(var)).or(value)
+	};
+	let index_dir = pick("index-dir", "INDEX_DIR", file.index_dir)
+	    .ok_or("index_dir is not set (set index_dir in the config file, INDEX_DIR or --index-dir)")?;
+	let res_dir = pick("res-dir", "RES_DIR", file.res_dir)
+	    .ok_or("res_dir is not set (set res_dir in the config file, RES_DIR or --res-dir)")?;
+	let date_pattern = pick("date-pattern", "DATE_PATTERN", file.date_pattern);
+	let mail_index_dir = pick("mail-index-dir", "MAIL_INDEX_DIR", file.mail_index_dir);
+
+	Ok(Config {
+	    index_dir,
+	    res_dir,
+	    roots: file.roots,
+	    date_pattern,
+	    formats: file.formats,
+	    split_by_heading: file.split_by_heading,
+	    encodings: file.encodings,
+	    filters: file.filters,
+	    filter_timeout: file.filter_timeout.unwrap_or(60),
+	    mail_index_dir,
+	    mailboxes: file.mailboxes,
+	})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+	let file: ConfigFile = toml::from_str(r#"
+index_dir = "/file/index"
+res_dir = "/file/res"
+roots = ["/diary/split"]
+listen = "ignored by rsdiary_add"
+split_by_heading = true
+mail_index_dir = "/file/mail"
+mailboxes = ["/home/masm/Maildir"]
+
+[formats]
+txt = "markdown"
+
+[filters]
+pdf = "pdftotext -enc UTF-8 {path} -"
+
+[encodings]
+"/diary/old" = "euc-jp"
+"#).unwrap();
+	let mut opts = HashMap::<String, String>::new();
+	opts.insert(String::from("index-dir"), String::from("/opt/index"));
+	let env = |key: &str| match key {
+	    "INDEX_DIR" => Some(String::from("/env/index")),
+	    "DATE_PATTERN" => Some(String::from("(?P<y>\\d{4})")),
+	    _ => None,
+	};
+	let config = Config::resolve(file, &opts, env).unwrap();
+	assert_eq!(config.index_dir, "/opt/index");
+	assert_eq!(config.res_dir, "/file/res");
+	assert_eq!(config.roots, vec![String::from("/diary/split")]);
+	assert_eq!(config.date_pattern.as_deref(), Some("(?P<y>\\d{4})"));
+	assert_eq!(config.formats["txt"], "markdown");
+	assert!(config.split_by_heading);
+	assert_eq!(config.encodings["/diary/old"], "euc-jp");
+	assert_eq!(config.filters["pdf"], "pdftotext -enc UTF-8 {path} -");
+	assert_eq!(config.filter_timeout, 60);
+	assert_eq!(config.mail_index_dir.as_deref(), Some("/file/mail"));
+	assert_eq!(config.mailboxes, vec![String::from("/home/masm/Maildir")]);
+
+	let mut args = vec![String::from("--res-dir"), String::from("/r"), String::from("a.est")];
+	let opts = take_options(&mut args, &OPTIONS, &[]).unwrap();
+	assert_eq!(args, vec![String::from("a.est")]);
+	let err = Config::resolve(ConfigFile::default(), &opts, |_| None);
+	assert!(err.is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::path::Path;
+use regex::Regex;
+
+// ファイル名から日付を取り出す正規表現。y, m, d の名前付きグループを使う。
+const DEFAULT_PATTERN: &str = r"(?P<y>\d{4})(?P<m>\d{2})(?P<d>\d{2})";
+
+// ヘッダとみなす行数
+const HEADER_LINES: usize = 10;
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+pub struct DateExtractor {
+    pattern: Regex,
+    header: Regex,
+    w3cdtf: Regex,
+    rfc1123: Regex,
+}
+
+impl DateExtractor {
+    pub fn new(pattern: Option<&str>) -> Result<Self, String> {
+	Self::with_pattern(pattern.unwrap_or(DEFAULT_PATTERN))
+    }
+
+    pub fn with_pattern(pattern: &str) -> Result<Self, String> {
+	let pattern = Regex::new(pattern).map_err(|why| format!("invalid date_pattern: {}", why))?;
+	let header = Regex::new(r"^(?:@cdate=|[Dd]ate:)\s*(?P<y>\d{4})[-/]?(?P<m>\d{1,2})[-/]?(?P<d>\d{1,2})").unwrap();
+	let w3cdtf = Regex::new(r"^(?P<y>\d{4})[-/](?P<m>\d{1,2})[-/](?P<d>\d{1,2})").unwrap();
+	let rfc1123 = Regex::new(r"(?P<d>\d{1,2})\s+(?P<mon>[A-Z][a-z]{2})\s+(?P<y>\d{4})").unwrap();
+	Ok(DateExtractor {
+	    pattern,
+	    header,
+	    w3cdtf,
+	    rfc1123,
+	})
+    }
+
+    // ファイル名から取れなければ、先頭の数行にある日付ヘッダを探す。
+    pub fn extract(&self, path: &str, buf: &str) -> Option<u32> {
+	self.path_date(path).or_else(|| self.header_date(buf))
+    }
+
+    pub fn path_date(&self, path: &str) -> Option<u32> {
+	let name = Path::new(path).file_name()?.to_str()?;
+	Self::to_date(&self.pattern, name)
+    }
+
+    pub fn header_date(&self, buf: &str) -> Option<u32> {
+	buf.lines()
+	    .take(HEADER_LINES)
+	    .find_map(|line| Self::to_date(&self.header, line.trim()))
+    }
+
+    // @cdate などの属性値。2022-08-05T10:00:00+09:00 か Fri, 05 Aug 2022 10:00:00 +0900
+    pub fn value_date(&self, value: &str) -> Option<u32> {
+	let value = value.trim();
+	if let Some(date) = Self::to_date(&self.w3cdtf, value) {
+	    return Some(date);
+	}
+	let caps = self.rfc1123.captures(value)?;
+	let y: u32 = caps["y"].parse().ok()?;
+	let m = MONTHS.iter().position(|mon| *mon == &caps["mon"])? as u32 + 1;
+	let d: u32 = caps["d"].parse().ok()?;
+	Self::make_date(y, m, d)
+    }
+
+    // YYYYMMDD 形式の整数にする
+    fn to_date(re: &Regex, s: &str) -> Option<u32> {
+	let caps = re.captures(s)?;
+	let y: u32 = caps.name("y")?.as_str().parse().ok()?;
+	let m: u32 = caps.name("m")?.as_str().parse().ok()?;
+	let d: u32 = caps.name("d")?.as_str().parse().ok()?;
+	Self::make_date(y, m, d)
+    }
+
+    fn make_date(y: u32, m: u32, d: u32) -> Option<u32> {
+	if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+	    return None;
+	}
+	Some(y * 10000 + m * 100 + d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let ex = DateExtractor::with_pattern(DEFAULT_PATTERN).unwrap();
+	assert_eq!(ex.extract("/home/masm/esdiary/split/202208/20220805p01.est", ""), Some(20220805));
+	assert_eq!(ex.extract("/tmp/memo.txt", "@title=memo\n@cdate=2022-08-05T10:00:00+09:00\n"), Some(20220805));
+	assert_eq!(ex.extract("/tmp/memo.txt", "Date: 2021/1/2\n"), Some(20210102));
+	assert_eq!(ex.extract("/tmp/memo.txt", "no date here\n"), None);
+	assert_eq!(ex.extract("/tmp/20221301.txt", ""), None);
+
+	let ex = DateExtractor::with_pattern(r"(?P<y>\d{4})-(?P<m>\d{2})-(?P<d>\d{2})\.md$").unwrap();
+	assert_eq!(ex.extract("/diary/2022-08-05.md", ""), Some(20220805));
+
+	assert_eq!(ex.value_date("2022-08-05T10:00:00+09:00"), Some(20220805));
+	assert_eq!(ex.value_date("Fri, 5 Aug 2022 10:00:00 +0900"), Some(20220805));
+	assert_eq!(ex.value_date("sometime"), None);
+
+	assert!(DateExtractor::new(Some("(?P<y>\\d{4}")).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use sha2::{Digest, Sha256};
+use wait_timeout::ChildExt;
+
+// 拡張子ごとの外部コマンド。標準出力を plain として索引に入れる。
+//   pdf = "pdftotext -enc UTF-8 {path} -"
+//   docx = "pandoc -t plain {path}"
+// {path} が無ければ最後にファイル名を付ける。引数は空白で区切るだけで、クォートは解釈しない。
+// 出力は cache_dir に、コマンドとファイルの中身の SHA-256 を名前にして取っておき、
+// 変わっていなければコマンドを実行しない。
+pub struct Filters {
+    map: HashMap<String, String>,
+    timeout: Duration,
+    cache_dir: PathBuf,
+}
+
+impl Filters {
+    pub fn new(config: &HashMap<String, String>, timeout: u64, cache_dir: &Path) -> Self {
+	let map = config.iter()
+	    .map(|(ext, command)| (ext.trim_start_matches('.').to_string(), command.clone()))
+	    .collect();
+	Filters {
+	    map,
+	    timeout: Duration::from_secs(timeout),
+	    cache_dir: cache_dir.to_path_buf(),
+	}
+    }
+
+    pub fn get(&self, path: &str) -> Option<&str> {
+	let ext = Path::new(path).extension().and_then(|s| s.to_str())?;
+	self.map.get(ext).map(|s| s.as_str())
+    }
+
+    // 出力を取っておくファイル。rsdiary_search もこれを読んで表示する。
+    pub fn cache_path(&self, command: &str, bytes: &[u8]) -> PathBuf {
+	let mut hasher = Sha256::new();
+	hasher.update(command.as_bytes());
+	hasher.update([0]);
+	hasher.update(bytes);
+	let hash: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+	self.cache_dir.join(format!("{}.txt", hash))
+    }
+
+    // 出力のバイト列。失敗したら理由を返す。
+    pub fn run(&self, command: &str, path: &str, bytes: &[u8]) -> Result<Vec<u8>, String> {
+	let cache = self.cache_path(command, bytes);
+	if let Ok(buf) = fs::read(&cache) {
+	    return Ok(buf);
+	}
+
+	let buf = self.exec(command, path)?;
+	// 次回のためなので、書けなくても続ける
+	if let Err(why) = fs::create_dir_all(&self.cache_dir).and_then(|_| fs::write(&cache, &buf)) {
+	    eprintln!("couldn't write {}: {}", cache.display(), why);
+	}
+	Ok(buf)
+    }
+
+    // keep に無いキャッシュを消す
+    pub fn prune(&self, keep: &HashSet<String>) {
+	let entries = match self.cache_dir.read_dir() {
+	    Ok(entries) => entries,
+	    Err(_) => return,
+	};
+	for entry in entries.flatten() {
+	    let path = entry.path();
+	    if keep.contains(path.to_string_lossy().as_ref()) {
+		continue;
+	    }
+	    if let Err(why) = fs::remove_file(&path) {
+		eprintln!("couldn't remove {}: {}", path.display(), why);
+	    }
+	}
+    }
+
+    fn exec(&self, command: &str, path: &str) -> Result<Vec<u8>, String> {
+	let mut args: Vec<String> = command.split_whitespace().map(|s| s.replace("{path}", path)).collect();
+	if !command.contains("{path}") {
+	    args.push(path.to_string());
+	}
+	if args.len() < 2 {
+	    return Err(format!("empty filter command for {}", path));
+	}
+	let mut child = Command::new(&args[0])
+	    .args(&args[1..])
+	    .stdin(Stdio::null())
+	    .stdout(Stdio::piped())
+	    .stderr(Stdio::piped())
+	    .spawn()
+	    .map_err(|why| format!("couldn't run {}: {}", args[0], why))?;
+
+	// パイプが詰まらないように、終わるのを待つ間も読んでおく
+	let mut stdout = child.stdout.take().unwrap();
+	let mut stderr = child.stderr.take().unwrap();
+	let out = thread::spawn(move || {
+	    let mut buf = Vec::<u8>::new();
+	    stdout.read_to_end(&mut buf).map(|_| buf)
+	});
+	let err = thread::spawn(move || {
+	    let mut buf = Vec::<u8>::new();
+	    let _ = stderr.read_to_end(&mut buf);
+	    buf
+	});
+
+	let status = match child.wait_timeout(self.timeout).map_err(|why| why.to_string())? {
+	    Some(status) => status,
+	    None => {
+		let _ = child.kill();
+		let _ = child.wait();
+		return Err(format!("{} timed out after {} seconds", args[0], self.timeout.as_secs()));
+	    },
+	};
+	let buf = out.join().unwrap().map_err(|why| format!("couldn't read output of {}: {}", args[0], why))?;
+	let err = err.join().unwrap();
+	if !status.success() {
+	    let msg = String::from_utf8_lossy(&err);
+	    return Err(format!("{} failed ({}): {}", args[0], status, msg.lines().next().unwrap_or("").trim()));
+	}
+	Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-filter-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let file = dir.join("20220805.pdf");
+	fs::write(&file, "東京へ\n").unwrap();
+	let path = file.to_str().unwrap();
+
+	let config = HashMap::from([
+	    (String::from("pdf"), String::from("cat")),
+	    (String::from(".doc"), String::from("tail -f {path}")),
+	]);
+	let filters = Filters::new(&config, 1, &dir.join("cache"));
+	assert_eq!(filters.get(path), Some("cat"));
+	assert_eq!(filters.get("/diary/20220805.doc"), Some("tail -f {path}"));
+	assert_eq!(filters.get("/diary/20220805.est"), None);
+
+	assert_eq!(filters.run("cat", path, b"1").unwrap(), "東京へ\n".as_bytes());
+	assert_eq!(fs::read_dir(dir.join("cache")).unwrap().count(), 1);
+	// 中身が同じならキャッシュを使う
+	fs::write(&file, "京都へ\n").unwrap();
+	assert_eq!(filters.run("cat", path, b"1").unwrap(), "東京へ\n".as_bytes());
+	assert_eq!(filters.run("cat", path, b"2").unwrap(), "京都へ\n".as_bytes());
+
+	assert!(filters.run("false", path, b"3").unwrap_err().contains("false failed"));
+	assert!(filters.run("tail -f {path}", path, b"4").unwrap_err().contains("timed out"));
+	assert!(filters.run("rsdiary-no-such-command", path, b"5").is_err());
+
+	// 使っていないキャッシュは消す
+	let keep = filters.cache_path("cat", b"2");
+	filters.prune(&HashSet::from([keep.to_string_lossy().to_string()]));
+	let rest: Vec<PathBuf> = fs::read_dir(dir.join("cache")).unwrap().map(|e| e.unwrap().path()).collect();
+	assert_eq!(rest, vec![keep]);
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+}
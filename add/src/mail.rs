@@ -0,0 +1,168 @@
+use std::path::Path;
+use rsdiary_common::entry::Entry;
+use rsdiary_common::html;
+use rsdiary_common::mime::{self, Message};
+use rsdiary_common::section::Section;
+use crate::date::DateExtractor;
+
+// メール。1通を1文書にする。
+// Maildir の cur/ new/ にあるファイルは1ファイル1通で、anchor は空 (path のまま登録する)。
+// それ以外は mbox とみなし、"From " 行で分けて path#m1, path#m2, ... で登録する。
+// Subject を題名に、Date を日付に、text/plain の本文 (無ければ text/html) を索引に入れる。
+// From は検索できるように hidden に入れる。
+
+pub fn read(path: &str, bytes: &[u8], date_extractor: &DateExtractor) -> Vec<Section> {
+    let lines: Vec<&[u8]> = bytes.split(|b| *b == b'\n').collect();
+    if is_maildir_message(path) {
+	return vec![message(bytes, String::new(), (1, lines.len()), date_extractor)];
+    }
+    let mut starts = Vec::<usize>::new();
+    for (i, line) in lines.iter().enumerate() {
+	if line.starts_with(b"From ") && (i == 0 || mime::trim_cr(lines[i - 1]).is_empty()) {
+	    starts.push(i);
+	}
+    }
+    let mut messages = Vec::<Section>::new();
+    for (n, start) in starts.iter().enumerate() {
+	let mut end = starts.get(n + 1).cloned().unwrap_or(lines.len());
+	while end > start + 1 && mime::trim_cr(lines[end - 1]).is_empty() {
+	    end -= 1;
+	}
+	let raw = mime::unescape_mbox(&lines[start + 1..end]);
+	let anchor = format!("m{}", n + 1);
+	messages.push(message(&raw, anchor, (start + 1, end), date_extractor));
+    }
+    messages
+}
+
+fn message(raw: &[u8], anchor: String, range: (usize, usize), date_extractor: &DateExtractor) -> Section {
+    let message = Message::parse(raw);
+
+    // text/plain を優先し、無ければ text/html からタグを除いたもの
+    let text = match message.part("text/plain") {
+	Some(text) => text.to_string(),
+	None => message.part("text/html").map(|text| html::parse(text).body).unwrap_or_default(),
+    };
+    let mut entry = Entry::default();
+    entry.body = text.lines()
+	.map(|line| line.trim_end())
+	.collect::<Vec<&str>>()
+	.join("\n")
+	.trim()
+	.to_string();
+    entry.title = match message.decoded_header("subject") {
+	Some(subject) if !subject.is_empty() => subject,
+	_ => entry.body.lines().next().unwrap_or("").to_string(),
+    };
+    // rsdiary_search はこれを見て MIME を戻して表示する
+    entry.attrs.push((String::from("format"), String::from("mail")));
+    if let Some(from) = message.decoded_header("from") {
+	entry.hidden = from.clone();
+	entry.attrs.push((String::from("from"), from));
+    }
+    if let Some(id) = message.header("message-id") {
+	entry.attrs.push((String::from("message-id"), id.trim().to_string()));
+    }
+    Section {
+	anchor,
+	date: message.header("date").and_then(|date| date_extractor.value_date(date)),
+	lines: range,
+	entry,
+    }
+}
+
+fn is_maildir_message(path: &str) -> bool {
+    let parent = Path::new(path).parent();
+    let name = parent.and_then(|p| p.file_name()).and_then(|s| s.to_str());
+    matches!(name, Some("cur") | Some("new"))
+	&& parent.and_then(|p| p.parent()).is_some_and(|dir| dir.join("tmp").is_dir() || dir.join("cur").is_dir())
+}
+
+// Maildir なら cur/ と new/ のファイル、そうでなければ mbox として扱う
+pub fn collect_mailboxes(path: &Path, files: &mut Vec<String>) {
+    if !path.is_dir() {
+	if let Some(s) = path.to_str() {
+	    files.push(s.to_string());
+	}
+	return;
+    }
+    let dirs = if path.join("cur").is_dir() {
+	vec![path.join("cur"), path.join("new")]
+    } else {
+	vec![path.to_path_buf()]
+    };
+    for dir in dirs {
+	let entries = match dir.read_dir() {
+	    Ok(entries) => entries,
+	    Err(why) => {
+		eprintln!("couldn't read {}: {}", dir.display(), why);
+		continue;
+	    },
+	};
+	for entry in entries.flatten() {
+	    if entry.file_name().to_string_lossy().starts_with('.') {
+		continue;
+	    }
+	    let path = entry.path();
+	    if path.is_dir() {
+		collect_mailboxes(&path, files);
+	    } else if let Some(s) = path.to_str() {
+		files.push(s.to_string());
+	    }
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mbox() {
+	// 「東京へ」「東京駅は混んでいた。」を ISO-2022-JP で
+	let subject = "=?ISO-2022-JP?B?GyRCRWw1fiRYGyhC?=";
+	let body = "GyRCRWw1fjFYJE86LiRzJEckJCQ/ISMbKEIK";
+	let buf = format!("From me@example.com Fri Aug  5 10:00:00 2022\n\
+			   From: =?UTF-8?Q?=E3=81=BE=E3=81=95?= <me@example.com>\n\
+			   Subject: {}\n\
+			   Date: Fri, 5 Aug 2022 10:00:00 +0900\n\
+			   Message-ID: <1@example.com>\n\
+			   MIME-Version: 1.0\n\
+			   Content-Type: multipart/alternative;\n \
+			   boundary=\"b1\"\n\
+			   \n\
+			   --b1\n\
+			   Content-Type: text/plain; charset=ISO-2022-JP\n\
+			   Content-Transfer-Encoding: base64\n\
+			   \n\
+			   {}\n\
+			   --b1\n\
+			   Content-Type: text/html; charset=utf-8\n\
+			   \n\
+			   <p>html</p>\n\
+			   --b1--\n\
+			   \n\
+			   From me@example.com Sat Aug  6 10:00:00 2022\n\
+			   Subject: memo\n\
+			   Date: Sat, 6 Aug 2022 09:00:00 +0900\n\
+			   Content-Type: text/plain; charset=utf-8\n\
+			   Content-Transfer-Encoding: quoted-printable\n\
+			   \n\
+			   =E9=9B=A8=E3=80=82 soft=\n\
+			   break\n\
+			   >From here\n", subject, body);
+	let messages = read("/mail/notes.mbox", buf.as_bytes(), &DateExtractor::new(None).unwrap());
+	assert_eq!(messages.len(), 2);
+	assert_eq!(messages[0].anchor, "m1");
+	assert_eq!(messages[0].date, Some(20220805));
+	assert_eq!(messages[0].lines, (1, 19));
+	assert_eq!(messages[0].entry.title, "東京へ");
+	assert_eq!(messages[0].entry.body, "東京駅は混んでいた。");
+	assert_eq!(messages[0].entry.hidden, "まさ <me@example.com>");
+	assert!(messages[0].entry.attrs.contains(&(String::from("message-id"), String::from("<1@example.com>"))));
+	assert_eq!(messages[1].anchor, "m2");
+	assert_eq!(messages[1].date, Some(20220806));
+	assert_eq!(messages[1].entry.title, "memo");
+	assert_eq!(messages[1].entry.body, "雨。 softbreak\nFrom here");
+    }
+}
@@ -1,3 +1,15 @@
+mod config;
+mod date;
+mod filter;
+mod mail;
+
+use rsdiary_common::decode;
+use rsdiary_common::entry::{self, Entry};
+use rsdiary_common::field;
+use rsdiary_common::reader;
+use rsdiary_common::section;
+use rsdiary_common::tdiary;
+
 use std::env;
 use std::path::PathBuf;
 use std::collections::{HashSet, HashMap};
@@ -6,29 +18,25 @@ use std::fs::File;
 use std::io::prelude::*;   // write_all
 use std::io::BufReader;
 use std::path::Path;
+use std::process;
 use sudachi::prelude::MorphemeList;
 use sudachi::config::Config;
 use sudachi::analysis::Mode;
 use sudachi::analysis::stateful_tokenizer::StatefulTokenizer;
 use sudachi::dic::dictionary::JapaneseDictionary;
 
-fn replace_lf(buf: &String) -> String {
-    buf.replace("\n", " ")
-	.replace("\r", " ")
-	.replace("\t", " ")
+fn replace_lf(buf: &str) -> String {
+    buf.replace(['\n', '\r', '\t'], " ")
 }
 
-fn get_dict() -> JapaneseDictionary {
-    let res_dir0 = env::var("RES_DIR").expect("Couldn't get RES_DIR");
-    let res_dir1 = env::var("RES_DIR").expect("Couldn't get RES_DIR");
-    let res_dir2 = env::var("RES_DIR").expect("Couldn't get RES_DIR");
-    let mut json = PathBuf::from(res_dir0);
+fn get_dict(res_dir: &str) -> JapaneseDictionary {
+    let mut json = PathBuf::from(res_dir);
     json.push("sudachi.json");
-    let mut sys_dic = PathBuf::from(res_dir2);
+    let mut sys_dic = PathBuf::from(res_dir);
     sys_dic.push("system.dic");
     let config = Config::new(
 	Some(json),
-	Some(PathBuf::from(res_dir1)),
+	Some(PathBuf::from(res_dir)),
 	Some(sys_dic),
     ).expect("Failed to load config file");
     JapaneseDictionary::from_cfg(&config).expect("Failed to read dict.")
@@ -55,9 +63,9 @@ fn tokenize(string: String, dict: &JapaneseDictionary) -> HashSet<String> {
     set
 }
 
-fn index_file_path(typ: &str, suffix: &str) -> String {
-    let mut path = env::var("INDEX_DIR").expect("Couldn't get INDEX_DIR");
-    path.push_str("/");
+fn index_file_path(dir: &str, typ: &str, suffix: &str) -> String {
+    let mut path = dir.to_string();
+    path.push('/');
     path.push_str("index.");
     path.push_str(typ);
     path.push_str(".txt");
@@ -65,31 +73,29 @@ fn index_file_path(typ: &str, suffix: &str) -> String {
     path
 }
 
-fn read_index_words() -> HashMap<String, u32> {
-    let path = index_file_path("words", "");
+fn read_index_words(dir: &str) -> HashMap<String, u32> {
+    let path = index_file_path(dir, "words", "");
     let path = Path::new(&path);
-    let file = match File::open(&path) {
+    let file = match File::open(path) {
 	Err(why) => panic!("couldn't open {}: {}", path.display(), why),
 	Ok(file) => file,
     };
     let file = BufReader::new(file);
 
     let mut map = HashMap::<String, u32>::new();
-    let mut word_id: u32 = 0;
 
-    for line in file.lines() {
+    for (word_id, line) in (0..).zip(file.lines()) {
 	let line = line.unwrap();
 	map.insert(line, word_id);
-	word_id += 1;
     }
 
     map
 }
 
-fn read_index_matrix() -> HashMap<String, HashSet<u32>> {
-    let path = index_file_path("matrix", "");
+fn read_index_matrix(dir: &str) -> HashMap<String, HashSet<u32>> {
+    let path = index_file_path(dir, "matrix", "");
     let path = Path::new(&path);
-    let file = File::open(&path).expect("Failed to open index.matrix.txt.");
+    let file = File::open(path).expect("Failed to open index.matrix.txt.");
     let file = BufReader::new(file);
 
     let mut mat = HashMap::<String, HashSet<u32>>::new();
@@ -109,10 +115,91 @@ fn read_index_matrix() -> HashMap<String, HashSet<u32>> {
     mat
 }
 
-fn write_index_words(words: HashMap<String, u32>) {
-    let path = index_file_path("words", ".new");
+// index.docs.txt の1行。検索結果はこれだけで表示できるようにする。
+// path \t date \t title \t length \t excerpt \t uri \t name=value ...
+struct Doc {
+    date: Option<u32>,
+    title: String,
+    length: usize,	// 本文の文字数
+    excerpt: String,	// 本文の先頭 EXCERPT_LEN 文字
+    uri: String,	// 空なら url_template で作る
+    attrs: Vec<(String, String)>,
+}
+
+const EXCERPT_LEN: usize = 400;
+
+impl Doc {
+    fn new(date: Option<u32>, entry: &Entry) -> Self {
+	let body = entry.body.trim();
+	Doc {
+	    date,
+	    title: entry.title.trim().to_string(),
+	    length: body.chars().count(),
+	    excerpt: body.chars().take(EXCERPT_LEN).collect(),
+	    uri: entry.uri.clone().unwrap_or_default(),
+	    attrs: entry.attrs.clone(),
+	}
+    }
+}
+
+// 見出しで分けた文書 (lines がある) は path#anchor で登録しているので、元のファイルを返す。
+// ファイル名にも # は使えるので、それ以外は分けない。
+fn source_file<'a>(path: &'a str, doc: Option<&Doc>) -> &'a str {
+    match path.rsplit_once('#') {
+	Some((file, _)) if doc.is_some_and(|doc| doc.attrs.iter().any(|(name, _)| name == "lines")) => file,
+	_ => path,
+    }
+}
+
+// 古いインデックスには index.docs.txt が無いので、その場合は空から始める。
+fn read_index_docs(dir: &str) -> HashMap<String, Doc> {
+    let path = index_file_path(dir, "docs", "");
+    let path = Path::new(&path);
+    let mut docs = HashMap::<String, Doc>::new();
+    let file = match File::open(path) {
+	Err(_) => return docs,
+	Ok(file) => file,
+    };
+    let file = BufReader::new(file);
+
+    for line in file.lines() {
+	let line = line.unwrap();
+	let mut iter = line.split('\t');
+	let path = iter.next().unwrap();
+	let date = iter.next().and_then(|s| s.parse().ok());
+	let title = field::unescape(iter.next().unwrap_or(""));
+	let length = iter.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+	let excerpt = field::unescape(iter.next().unwrap_or(""));
+	let uri = field::unescape(iter.next().unwrap_or(""));
+	let attrs = iter.filter_map(|s| {
+	    let (name, value) = s.split_once('=')?;
+	    Some((field::unescape(name), field::unescape(value)))
+	}).collect();
+	docs.insert(path.to_string(), Doc { date, title, length, excerpt, uri, attrs });
+    }
+
+    docs
+}
+
+type Index = (HashMap<String, u32>, HashMap<String, HashSet<u32>>, HashMap<String, Doc>);
+
+// create なら dir を作り、まだ索引が無ければ空から始める (メール用の索引や --import-est の初回)
+fn load_index(dir: &str, create: bool) -> Index {
+    if create {
+	if let Err(why) = fs::create_dir_all(dir) {
+	    panic!("couldn't create {}: {}", dir, why);
+	}
+	if !Path::new(&index_file_path(dir, "words", "")).exists() {
+	    return (HashMap::new(), HashMap::new(), HashMap::new());
+	}
+    }
+    (read_index_words(dir), read_index_matrix(dir), read_index_docs(dir))
+}
+
+fn write_index_words(dir: &str, words: HashMap<String, u32>) {
+    let path = index_file_path(dir, "words", ".new");
     let path = Path::new(&path);
-    let mut file = File::create(&path).expect("Failed to create index.words.txt.new.");
+    let mut file = File::create(path).expect("Failed to create index.words.txt.new.");
 
     let mut max_id = 0;
     for (_, id) in words.iter() {
@@ -132,10 +219,10 @@ fn write_index_words(words: HashMap<String, u32>) {
     }
 }
 
-fn write_index_matrix(mat: HashMap::<String, HashSet<u32>>) {
-    let path = index_file_path("matrix", ".new");
+fn write_index_matrix(dir: &str, mat: HashMap::<String, HashSet<u32>>) {
+    let path = index_file_path(dir, "matrix", ".new");
     let path = Path::new(&path);
-    let mut file = File::create(&path).expect("Failed to create index.matrix.txt.new.");
+    let mut file = File::create(path).expect("Failed to create index.matrix.txt.new.");
 
     for (fname, word_ids) in mat.iter() {
 	file.write_all(fname.as_bytes()).expect("Failed to write filename.");
@@ -149,65 +236,534 @@ fn write_index_matrix(mat: HashMap::<String, HashSet<u32>>) {
     }
 }
 
-fn rename_index() {
-    let src = index_file_path("words", ".old");
+fn write_index_docs(dir: &str, docs: HashMap<String, Doc>) {
+    let path = index_file_path(dir, "docs", ".new");
+    let path = Path::new(&path);
+    let mut file = File::create(path).expect("Failed to create index.docs.txt.new.");
+
+    for (fname, doc) in docs.iter() {
+	file.write_all(fname.as_bytes()).expect("Failed to write filename.");
+	file.write_all(b"\t").expect("Failed to write delimiter.");
+	if let Some(date) = doc.date {
+	    file.write_all(date.to_string().as_bytes()).expect("Failed to write date.");
+	}
+	let mut fields = format!("\t{}\t{}\t{}\t{}", field::escape(&doc.title), doc.length,
+				 field::escape(&doc.excerpt), field::escape(&doc.uri));
+	for (name, value) in doc.attrs.iter() {
+	    fields.push_str(&format!("\t{}={}", field::escape(name), field::escape(value)));
+	}
+	fields.push('\n');
+	file.write_all(fields.as_bytes()).expect("Failed to write fields.");
+    }
+}
+
+fn rename_index(dir: &str) {
+    let src = index_file_path(dir, "words", ".old");
     if let Err(why) = fs::remove_file(src) {
-	eprintln!("couldn't remove {}: {}", "index.words.txt.old", why);
+	eprintln!("couldn't remove index.words.txt.old: {}", why);
     }
-    let src = index_file_path("matrix", ".old");
+    let src = index_file_path(dir, "matrix", ".old");
     if let Err(why) = fs::remove_file(src) {
-	eprintln!("couldn't remove {}: {}", "index.matrix.txt.old", why);
+	eprintln!("couldn't remove index.matrix.txt.old: {}", why);
     }
-    let src = index_file_path("words", "");
-    let dst = index_file_path("words", ".old");
-    fs::rename(src, dst).expect("rename failed");
-    let src = index_file_path("matrix", "");
-    let dst = index_file_path("matrix", ".old");
+    let src = index_file_path(dir, "docs", ".old");
+    if let Err(why) = fs::remove_file(src) {
+	eprintln!("couldn't remove index.docs.txt.old: {}", why);
+    }
+    // --import-est で新しく作る時は元の索引が無い
+    let src = index_file_path(dir, "words", "");
+    let dst = index_file_path(dir, "words", ".old");
+    if Path::new(&src).exists() {
+	fs::rename(src, dst).expect("rename failed");
+    }
+    let src = index_file_path(dir, "matrix", "");
+    let dst = index_file_path(dir, "matrix", ".old");
+    if Path::new(&src).exists() {
+	fs::rename(src, dst).expect("rename failed");
+    }
+    let src = index_file_path(dir, "docs", "");
+    let dst = index_file_path(dir, "docs", ".old");
+    if let Err(why) = fs::rename(src, dst) {
+	eprintln!("couldn't rename index.docs.txt: {}", why);
+    }
+    let src = index_file_path(dir, "words", ".new");
+    let dst = index_file_path(dir, "words", "");
     fs::rename(src, dst).expect("rename failed");
-    let src = index_file_path("words", ".new");
-    let dst = index_file_path("words", "");
+    let src = index_file_path(dir, "matrix", ".new");
+    let dst = index_file_path(dir, "matrix", "");
     fs::rename(src, dst).expect("rename failed");
-    let src = index_file_path("matrix", ".new");
-    let dst = index_file_path("matrix", "");
+    let src = index_file_path(dir, "docs", ".new");
+    let dst = index_file_path(dir, "docs", "");
     fs::rename(src, dst).expect("rename failed");
 }
 
-fn main() {
-    let dict = get_dict();
+fn get_word_ids(set: &HashSet<String>, index_words: &mut HashMap<String, u32>) -> HashSet<u32> {
+    let mut word_ids = HashSet::<u32>::new();
+    for word in set.iter() {
+	let word_id: u32 = match index_words.get(word) {
+	    Some(id) => *id,
+	    None => {
+		let new_id = index_words.len() as u32;
+		index_words.insert(word.clone(), new_id);
+		new_id
+	    }
+	};
 
-    let mut index_words = read_index_words();
-    let mut index_matrix = read_index_matrix();
+	word_ids.insert(word_id);
+    }
+    word_ids
+}
 
-    for inp in &env::args().collect::<Vec<String>>()[1..] {
-	let inp_path = Path::new(&inp);
-	let mut file = File::open(&inp_path).expect("Failed to open file.");
+// rsdiary_search が見つけた、無くなったファイルを索引から消す。
+// 読み込んだ index.stale.txt のパスを返す。
+fn prune_stale(dir: &str,
+	       index_matrix: &mut HashMap<String, HashSet<u32>>,
+	       index_docs: &mut HashMap<String, Doc>) -> Option<String> {
+    let path = index_file_path(dir, "stale", "");
+    let buf = fs::read_to_string(&path).ok()?;
+    for stale in buf.lines() {
+	let file = source_file(stale, index_docs.get(stale));
+	if stale.is_empty() || Path::new(file).exists() {
+	    continue;
+	}
+	if index_matrix.remove(stale).is_some() {
+	    eprintln!("pruned {}", stale);
+	}
+	index_docs.remove(stale);
+    }
+    Some(path)
+}
 
-	let mut buf = String::new();
-	if let Err(why) = file.read_to_string(&mut buf) {
-	    panic!("couldn't read {}: {}", inp_path.display(), why);
+// 文書の指定が無ければ、設定の roots 以下のファイルを全部読む
+fn collect_files(dir: &Path, files: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+	Ok(entries) => entries,
+	Err(why) => {
+	    eprintln!("couldn't read {}: {}", dir.display(), why);
+	    return;
+	},
+    };
+    for entry in entries {
+	let entry = entry.expect("Failed to read directory entry.");
+	let path = entry.path();
+	if entry.file_name().to_string_lossy().starts_with('.') {
+	    continue;
 	}
-	let buf = replace_lf(&buf);
-	
-	let set = tokenize(buf, &dict);
-	
-	let mut word_ids = HashSet::<u32>::new();
-	for word in set.iter() {
-	    let word_id: u32 = match index_words.get(word) {
-		Some(id) => *id,
-		None => {
-		    let new_id = index_words.len() as u32;
-		    index_words.insert(word.clone(), new_id);
-		    new_id
-		}
+	if path.is_dir() {
+	    collect_files(&path, files);
+	} else if let Some(s) = path.to_str() {
+	    files.push(s.to_string());
+	}
+    }
+}
+
+// --import-est で読むのは roots 以下のドラフトだけ
+fn collect_drafts(roots: &[String]) -> Vec<String> {
+    let mut files = Vec::<String>::new();
+    for root in roots.iter() {
+	collect_files(Path::new(root), &mut files);
+    }
+    files.retain(|f| f.ends_with(".est"));
+    files.sort();
+    files
+}
+
+// 属性の作成日時、無ければ更新日時
+fn attr_date(entry: &Entry, date_extractor: &date::DateExtractor) -> Option<u32> {
+    ["date", "cdate", "mdate"].iter().find_map(|name| {
+	let (_, value) = entry.attrs.iter().find(|(n, _)| n == name)?;
+	date_extractor.value_date(value)
+    })
+}
+
+// ファイルを文書に分けて読むのに要るもの
+struct Readers {
+    date_extractor: date::DateExtractor,
+    formats: reader::Formats,
+    decoder: decode::Decoder,
+    filters: filter::Filters,
+    splitter: section::Splitter,
+    split_by_heading: bool,
+    mail: bool,
+}
+
+impl Readers {
+    // (path, 文書, 索引に入れる文字列) の組。変換に失敗したら None。
+    fn read(&self, inp: &str, bytes: &[u8]) -> Option<Vec<(String, Doc, String)>> {
+	let mut parts = Vec::<(String, Doc, String)>::new();
+	let mut cache = None::<PathBuf>;
+	let (preamble, sections) = if self.mail {
+	    // メールは文字コードがパートごとに違うので、バイト列のまま読む
+	    (None, mail::read(inp, bytes, &self.date_extractor))
+	} else {
+	    // 変換するものは、コマンドの出力を plain として読む
+	    let command = self.filters.get(inp);
+	    let buf = match command {
+		Some(command) => match self.filters.run(command, inp, bytes) {
+		    Ok(out) => {
+			cache = Some(self.filters.cache_path(command, bytes));
+			self.decoder.decode_detected(inp, &out)
+		    },
+		    Err(msg) => {
+			eprintln!("couldn't convert {}: {}", inp, msg);
+			return None;
+		    },
+		},
+		None => self.decoder.decode(inp, bytes),
+	    };
+	    let format = if command.is_some() { reader::Format::Plain } else { self.formats.get(inp) };
+	    let mut entry = if command.is_some() { entry::plain(&buf) } else { self.formats.read(inp, &buf) };
+	    // 元のファイルはバイナリなので、rsdiary_search は変換結果を表示する
+	    if let Some(cache) = &cache {
+		entry.attrs.push((String::from("filtered"), cache.to_string_lossy().to_string()));
+	    }
+	    let date = self.date_extractor.extract(inp, &buf).or_else(|| attr_date(&entry, &self.date_extractor));
+	    // tDiary のデータは設定によらず1日ずつに分ける
+	    let split = if format == reader::Format::Tdiary {
+		Some((Entry::default(), tdiary::split(&buf)))
+	    } else if self.split_by_heading && section::Splitter::can_split(format) {
+		self.splitter.split(&buf, format, date)
+	    } else {
+		None
+	    };
+	    match split {
+		Some((preamble, sections)) => {
+		    let keep = !preamble.body.trim().is_empty();
+		    (keep.then_some((date, preamble)), sections)
+		},
+		None => (Some((date, entry)), vec![]),
+	    }
+	};
+	if let Some((date, entry)) = preamble {
+	    parts.push((inp.to_string(), Doc::new(date, &entry), entry.text()));
+	}
+	for section in sections {
+	    let mut doc = Doc::new(section.date, &section.entry);
+	    // anchor が空なのは1ファイル1通のメール
+	    let path = if section.anchor.is_empty() {
+		inp.to_string()
+	    } else {
+		doc.attrs.push((String::from("lines"), format!("{}-{}", section.lines.0, section.lines.1)));
+		format!("{}#{}", inp, section.anchor)
 	    };
+	    parts.push((path, doc, section.entry.text()));
+	}
+	Some(parts)
+    }
+}
+
+// inp を読み直して索引の分を入れ替える。
+// 読めない、変換できないファイルは、前回の分を残したまま飛ばして false を返す。
+fn update_file(inp: &str, readers: &Readers, dict: &JapaneseDictionary,
+	       index_words: &mut HashMap<String, u32>,
+	       index_matrix: &mut HashMap<String, HashSet<u32>>,
+	       index_docs: &mut HashMap<String, Doc>) -> bool {
+    let bytes = match fs::read(inp) {
+	Ok(bytes) => bytes,
+	Err(why) => {
+	    eprintln!("couldn't read {}: {}", inp, why);
+	    return false;
+	},
+    };
+    let parts = match readers.read(inp, &bytes) {
+	Some(parts) => parts,
+	None => return false,
+    };
+
+    // 前回の分 (分割したものは path#anchor) を消してから入れる
+    index_matrix.retain(|path, _| source_file(path, index_docs.get(path)) != inp);
+    index_docs.retain(|path, doc| source_file(path, Some(doc)) != inp);
+    for (path, doc, text) in parts {
+	let set = tokenize(replace_lf(&text), dict);
+	let word_ids = get_word_ids(&set, index_words);
+	index_matrix.insert(path.clone(), word_ids);
+	index_docs.insert(path, doc);
+    }
+    true
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let config = match config::Config::load(&mut args) {
+	Ok(config) => config,
+	Err(msg) => {
+	    eprintln!("rsdiary_add: {}", msg);
+	    process::exit(1);
+	},
+    };
+    // --import-est [DIR...]: estcmd のドラフトのディレクトリを今の索引に足す
+    // --mail [MAILBOX...]: mbox か Maildir のメールを mail_index_dir の索引に入れる
+    let import = args.first().is_some_and(|arg| arg == "--import-est");
+    let mail = args.first().is_some_and(|arg| arg == "--mail");
+    if import || mail {
+	args.remove(0);
+    }
+    let files = if mail {
+	let mailboxes = if args.is_empty() { &config.mailboxes } else { &args };
+	let mut files = Vec::<String>::new();
+	for mailbox in mailboxes.iter() {
+	    mail::collect_mailboxes(Path::new(mailbox), &mut files);
+	}
+	files.sort();
+	files
+    } else if import {
+	collect_drafts(if args.is_empty() { &config.roots } else { &args })
+    } else if args.is_empty() {
+	let mut files = Vec::<String>::new();
+	for root in config.roots.iter() {
+	    collect_files(Path::new(root), &mut files);
+	}
+	files.sort();
+	files
+    } else {
+	args
+    };
+    let dir = if mail {
+	match config.mail_index_dir.as_deref() {
+	    Some(dir) => dir,
+	    None => {
+		eprintln!("rsdiary_add: mail_index_dir is not set (set mail_index_dir in the config file, MAIL_INDEX_DIR or --mail-index-dir)");
+		process::exit(1);
+	    },
+	}
+    } else {
+	&config.index_dir[..]
+    };
+
+    let dict = get_dict(&config.res_dir);
+    let date_extractor = match date::DateExtractor::new(config.date_pattern.as_deref()) {
+	Ok(date_extractor) => date_extractor,
+	Err(msg) => {
+	    eprintln!("rsdiary_add: {}", msg);
+	    process::exit(1);
+	},
+    };
+    let formats = match reader::Formats::new(&config.formats) {
+	Ok(formats) => formats,
+	Err(msg) => {
+	    eprintln!("rsdiary_add: {}", msg);
+	    process::exit(1);
+	},
+    };
+    let decoder = match decode::Decoder::new(&config.encodings) {
+	Ok(decoder) => decoder,
+	Err(msg) => {
+	    eprintln!("rsdiary_add: {}", msg);
+	    process::exit(1);
+	},
+    };
+
+    // 取り込みでも、他の文書とその変換結果を消さないよう今の索引に足す
+    let (mut index_words, mut index_matrix, mut index_docs) = load_index(dir, mail || import);
+    let stale = prune_stale(dir, &mut index_matrix, &mut index_docs);
+    let readers = Readers {
+	date_extractor,
+	formats,
+	decoder,
+	filters: filter::Filters::new(&config.filters, config.filter_timeout, &Path::new(dir).join("filter-cache")),
+	splitter: section::Splitter::new(),
+	split_by_heading: config.split_by_heading,
+	mail,
+    };
+
+    let mut updated = 0;
+    for inp in files.iter() {
+	if update_file(inp, &readers, &dict, &mut index_words, &mut index_matrix, &mut index_docs) {
+	    updated += 1;
+	}
+    }
+    if import {
+	eprintln!("imported {} documents", updated);
+    }
+    if mail {
+	// Maildir はフラグが変わるとファイル名が変わるので、無くなったものは消す
+	index_matrix.retain(|path, _| Path::new(source_file(path, index_docs.get(path))).exists());
+	index_docs.retain(|path, _| index_matrix.contains_key(path));
+    } else {
+	// どの文書からも参照されなくなった変換結果は消す
+	let keep = index_docs.values()
+	    .flat_map(|doc| doc.attrs.iter())
+	    .filter(|(name, _)| name == "filtered")
+	    .map(|(_, value)| value.clone())
+	    .collect::<HashSet<String>>();
+	readers.filters.prune(&keep);
+    }
+
+    write_index_words(dir, index_words);
+    write_index_matrix(dir, index_matrix);
+    write_index_docs(dir, index_docs);
+
+    rename_index(dir);
+    if let Some(stale) = stale {
+	if let Err(why) = fs::remove_file(&stale) {
+	    eprintln!("couldn't remove {}: {}", stale, why);
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields() {
+	let doc = Doc::new(Some(20220805), &entry::plain(" 東京へ \n\n東京駅は混んでいた。\n帰った。\n"));
+	assert_eq!(doc.title, "東京へ");
+	assert_eq!(doc.length, 15);
+	assert_eq!(doc.excerpt, "東京駅は混んでいた。\n帰った。");
+    }
+    #[test]
+    fn mail_index() {
+	let root = std::env::temp_dir().join(format!("rsdiary-mail-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&root);
+	let dir = root.join("mail-index");
+	let dir = dir.to_str().unwrap();
+
+	// 初回は索引も dir も無い
+	let (mut words, mut matrix, mut docs) = load_index(dir, true);
+	assert!(words.is_empty() && matrix.is_empty() && docs.is_empty());
+
+	let mbox = "From me@example.com Fri Aug  5 10:00:00 2022\n\
+		    Subject: memo\n\
+		    Date: Fri, 5 Aug 2022 10:00:00 +0900\n\
+		    \n\
+		    東京へ。\n";
+	let extractor = date::DateExtractor::new(None).unwrap();
+	for message in mail::read("/mail/notes.mbox", mbox.as_bytes(), &extractor) {
+	    let path = format!("/mail/notes.mbox#{}", message.anchor);
+	    let set = HashSet::from([String::from("東京")]);
+	    matrix.insert(path.clone(), get_word_ids(&set, &mut words));
+	    docs.insert(path, Doc::new(message.date, &message.entry));
+	}
+	write_index_words(dir, words);
+	write_index_matrix(dir, matrix);
+	write_index_docs(dir, docs);
+	rename_index(dir);
+
+	let (words, matrix, docs) = load_index(dir, true);
+	assert_eq!(words["東京"], 0);
+	assert!(matrix["/mail/notes.mbox#m1"].contains(&0));
+	assert_eq!(docs["/mail/notes.mbox#m1"].title, "memo");
+	assert_eq!(docs["/mail/notes.mbox#m1"].date, Some(20220805));
+
+	fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn stale() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-add-stale-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let live = dir.join("C#メモ.txt").to_str().unwrap().to_string();
+	fs::write(&live, "メモ\n").unwrap();
+	let gone = dir.join("gone.org#2022-08-05").to_str().unwrap().to_string();
 
-	    word_ids.insert(word_id);
+	let mut matrix = HashMap::new();
+	let mut docs = HashMap::new();
+	for path in [&live, &gone] {
+	    matrix.insert(path.clone(), HashSet::from([0]));
+	    docs.insert(path.clone(), Doc::new(None, &entry::plain("メモ\n")));
 	}
-	index_matrix.insert(inp.clone(), word_ids);
+	docs.get_mut(&gone).unwrap().attrs.push((String::from("lines"), String::from("3-6")));
+	fs::write(index_file_path(dir.to_str().unwrap(), "stale", ""), format!("{}\n{}\n", live, gone)).unwrap();
+
+	// 名前に # があっても、ファイルが残っていれば消さない
+	assert!(prune_stale(dir.to_str().unwrap(), &mut matrix, &mut docs).is_some());
+	assert!(matrix.contains_key(&live) && docs.contains_key(&live));
+	assert!(!matrix.contains_key(&gone) && !docs.contains_key(&gone));
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn readers(filters: &[(&str, &str)], cache_dir: &Path) -> Readers {
+	let filters = filters.iter().map(|(ext, command)| (ext.to_string(), command.to_string())).collect();
+	Readers {
+	    date_extractor: date::DateExtractor::new(None).unwrap(),
+	    formats: reader::Formats::new(&HashMap::new()).unwrap(),
+	    decoder: decode::Decoder::new(&HashMap::new()).unwrap(),
+	    filters: filter::Filters::new(&filters, 10, cache_dir),
+	    splitter: section::Splitter::new(),
+	    split_by_heading: false,
+	    mail: false,
+	}
+    }
+
+    #[test]
+    fn import_est() {
+	let root = std::env::temp_dir().join(format!("rsdiary-import-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&root);
+	let drafts = root.join("drafts");
+	fs::create_dir_all(drafts.join("2022")).unwrap();
+	let draft = drafts.join("2022/20220805p01.est").to_str().unwrap().to_string();
+	fs::write(&draft, "@uri=http://localhost/20220805p01\n\
+			   @title=東京へ\n\
+			   @mdate=2022-08-06T09:00:00+09:00\n\
+			   @weather=雨\n\
+			   \n\
+			   東京駅は混んでいた。\n").unwrap();
+	fs::write(drafts.join("memo.txt"), "メモ\n").unwrap();
+	let dir = root.join("index");
+	let dir = dir.to_str().unwrap();
+
+	// 取り込む前からある文書
+	let (mut words, mut matrix, mut docs) = load_index(dir, true);
+	matrix.insert(String::from("/diary/other.txt"), HashSet::new());
+	docs.insert(String::from("/diary/other.txt"), Doc::new(None, &entry::plain("ほか\n")));
+
+	let files = collect_drafts(&[drafts.to_str().unwrap().to_string()]);
+	assert_eq!(files, vec![draft.clone()]);
+	let readers = readers(&[], &root.join("filter-cache"));
+	let dict = get_dict("../t/sudachi.rs/resources");
+	for inp in files.iter() {
+	    assert!(update_file(inp, &readers, &dict, &mut words, &mut matrix, &mut docs));
+	}
+	write_index_words(dir, words);
+	write_index_matrix(dir, matrix);
+	write_index_docs(dir, docs);
+	rename_index(dir);
+
+	let (_, matrix, docs) = load_index(dir, true);
+	assert!(matrix.contains_key("/diary/other.txt") && docs.contains_key("/diary/other.txt"));
+	let doc = &docs[&draft];
+	assert_eq!(doc.title, "東京へ");
+	assert_eq!(doc.uri, "http://localhost/20220805p01");
+	assert_eq!(doc.date, Some(20220805));
+	assert_eq!(doc.attrs, vec![
+	    (String::from("mdate"), String::from("2022-08-06T09:00:00+09:00")),
+	    (String::from("weather"), String::from("雨")),
+	]);
+
+	fs::remove_dir_all(&root).unwrap();
     }
 
-    write_index_words(index_words);
-    write_index_matrix(index_matrix);
+    #[test]
+    fn failed_filter() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-add-filter-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let inp = dir.join("20220805.pdf").to_str().unwrap().to_string();
+	fs::write(&inp, "東京へ\n").unwrap();
+	let readers = |command: &str| readers(&[("pdf", command)], &dir.join("filter-cache"));
+	let dict = get_dict("../t/sudachi.rs/resources");
 
-    rename_index();
+	// 前回は変換できていた
+	let mut words = HashMap::from([(String::from("東京"), 0)]);
+	let mut matrix = HashMap::from([(inp.clone(), HashSet::from([0]))]);
+	let mut previous = Doc::new(Some(20220805), &entry::plain("東京へ\n"));
+	previous.attrs.push((String::from("filtered"), String::from("/old/cache.txt")));
+	let mut docs = HashMap::from([(inp.clone(), previous)]);
+
+	// 変換に失敗したら前回の分を残す
+	update_file(&inp, &readers("false"), &dict, &mut words, &mut matrix, &mut docs);
+	assert!(matrix[&inp].contains(&0));
+	assert_eq!(docs[&inp].attrs, vec![(String::from("filtered"), String::from("/old/cache.txt"))]);
+
+	// 変換できれば入れ替える
+	update_file(&inp, &readers("cat"), &dict, &mut words, &mut matrix, &mut docs);
+	assert_eq!(docs[&inp].title, "東京へ");
+	assert_ne!(docs[&inp].attrs, vec![(String::from("filtered"), String::from("/old/cache.txt"))]);
+	assert_eq!(docs.len(), 1);
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
 }
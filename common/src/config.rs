@@ -0,0 +1,116 @@
+use std::env;
+use std::fs;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::de::DeserializeOwned;
+
+// 設定ファイルとコマンドラインのオプション。項目は rsdiary_add と rsdiary_search がそれぞれ決める。
+
+pub fn read_config_file<T: DeserializeOwned>(path: &PathBuf) -> Result<T, String> {
+    let buf = fs::read_to_string(path)
+	.map_err(|why| format!("couldn't read {}: {}", path.display(), why))?;
+    toml::from_str(&buf)
+	.map_err(|why| format!("couldn't parse {}: {}", path.display(), why))
+}
+
+// $XDG_CONFIG_HOME/rsdiary/config.toml か ~/.config/rsdiary/config.toml
+pub fn default_config_path() -> Option<PathBuf> {
+    let mut path = match env::var("XDG_CONFIG_HOME") {
+	Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+	_ => {
+	    let mut home = PathBuf::from(env::var("HOME").ok()?);
+	    home.push(".config");
+	    home
+	},
+    };
+    path.push("rsdiary");
+    path.push("config.toml");
+    Some(path)
+}
+
+// --config か RSDIARY_CONFIG で指定したもの、無ければ既定の場所にあれば読む
+pub fn load_config_file<T: DeserializeOwned + Default>(opts: &HashMap<String, String>) -> Result<T, String> {
+    let path = match opts.get("config") {
+	Some(path) => Some(PathBuf::from(path)),
+	None => env::var("RSDIARY_CONFIG").ok().map(PathBuf::from),
+    };
+    match path {
+	Some(path) => read_config_file(&path),
+	None => match default_config_path() {
+	    Some(path) if path.exists() => read_config_file(&path),
+	    _ => Ok(T::default()),
+	},
+    }
+}
+
+// --name value と --name=value を受け付ける。flags は値を取らず "true" になる。
+// 取り除いた残りは args に戻す。
+pub fn take_options(args: &mut Vec<String>, names: &[&str], flags: &[&str]) -> Result<HashMap<String, String>, String> {
+    let mut opts = HashMap::<String, String>::new();
+    let mut rest = Vec::<String>::new();
+    let mut iter = args.drain(..);
+    while let Some(arg) = iter.next() {
+	if arg == "--" {
+	    rest.push(arg);
+	    rest.extend(iter.by_ref());
+	    break;
+	}
+	let (name, value) = match arg.strip_prefix("--") {
+	    Some(s) => match s.split_once('=') {
+		Some((name, value)) => (name.to_string(), Some(value.to_string())),
+		None => (s.to_string(), None),
+	    },
+	    None => {
+		rest.push(arg);
+		continue;
+	    },
+	};
+	if value.is_none() && flags.contains(&name.as_str()) {
+	    opts.insert(name, String::from("true"));
+	    continue;
+	}
+	if !names.contains(&name.as_str()) {
+	    rest.push(arg);
+	    continue;
+	}
+	let value = match value {
+	    Some(value) => value,
+	    None => iter.next().ok_or(format!("--{} requires a value", name))?,
+	};
+	opts.insert(name, value);
+    }
+    drop(iter);
+    *args = rest;
+    Ok(opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+	list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn options() {
+	let names = ["config", "index-dir", "res-dir", "listen"];
+	let flags = ["dev"];
+	let mut a = args(&["--listen", "127.0.0.1:8080", "-f", "json", "--dev", "--index-dir=/idx", "東京", "--", "--res-dir"]);
+	let opts = take_options(&mut a, &names, &flags).unwrap();
+	assert_eq!(a, args(&["-f", "json", "東京", "--", "--res-dir"]));
+	assert_eq!(opts["listen"], "127.0.0.1:8080");
+	assert_eq!(opts["index-dir"], "/idx");
+	assert_eq!(opts["dev"], "true");
+	assert_eq!(opts.len(), 3);
+
+	// flags に無ければ値を取る
+	let mut a = args(&["--dev", "--res-dir", "/r", "a.est"]);
+	let opts = take_options(&mut a, &names, &[]).unwrap();
+	assert_eq!(a, args(&["--dev", "a.est"]));
+	assert_eq!(opts["res-dir"], "/r");
+
+	let mut a = args(&["--config"]);
+	assert!(take_options(&mut a, &names, &flags).is_err());
+    }
+}
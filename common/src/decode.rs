@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+
+// 古い日記は EUC-JP や Shift_JIS なので、UTF-8 でなければ文字コードを推測して変換する。
+// 設定の [encodings] でディレクトリごとに固定できる。長く一致したものを使う。
+pub struct Decoder {
+    overrides: Vec<(String, &'static Encoding)>,
+}
+
+impl Decoder {
+    pub fn new(config: &HashMap<String, String>) -> Result<Self, String> {
+	let mut overrides = Vec::<(String, &'static Encoding)>::new();
+	for (dir, label) in config.iter() {
+	    let encoding = Encoding::for_label(label.as_bytes())
+		.ok_or(format!("unknown encoding for {}: {}", dir, label))?;
+	    overrides.push((dir.trim_end_matches('/').to_string(), encoding));
+	}
+	overrides.sort_by_key(|(dir, _)| std::cmp::Reverse(dir.len()));
+	Ok(Decoder { overrides })
+    }
+
+    fn lookup(&self, path: &str) -> Option<&'static Encoding> {
+	self.overrides.iter()
+	    .find(|(dir, _)| path.strip_prefix(dir.as_str()).is_some_and(|rest| rest.starts_with('/')))
+	    .map(|(_, encoding)| *encoding)
+    }
+
+    pub fn decode(&self, path: &str, bytes: &[u8]) -> String {
+	let encoding = self.lookup(path).unwrap_or_else(|| Self::detect(bytes));
+	Self::convert(path, encoding, bytes)
+    }
+
+    // 変換コマンドの出力など、ファイルのあるディレクトリの指定が当てはまらないもの
+    pub fn decode_detected(&self, path: &str, bytes: &[u8]) -> String {
+	Self::convert(path, Self::detect(bytes), bytes)
+    }
+
+    fn detect(bytes: &[u8]) -> &'static Encoding {
+	if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+	    encoding
+	} else if std::str::from_utf8(bytes).is_ok() {
+	    UTF_8
+	} else {
+	    let mut detector = EncodingDetector::new();
+	    detector.feed(bytes, true);
+	    detector.guess(None, true)
+	}
+    }
+
+    fn convert(path: &str, encoding: &'static Encoding, bytes: &[u8]) -> String {
+	let (text, used, had_errors) = encoding.decode(bytes);
+	if had_errors {
+	    eprintln!("{}: some characters could not be decoded as {}", path, used.name());
+	}
+	text.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let decoder = Decoder::new(&HashMap::new()).unwrap();
+	let text = "今日は東京へ行った。帰りは雨が降っていて、傘を持っていなかったので濡れてしまった。";
+	let (euc, _, _) = encoding_rs::EUC_JP.encode(text);
+	let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode(text);
+	assert_eq!(decoder.decode("/d/a.txt", text.as_bytes()), text);
+	assert_eq!(decoder.decode("/d/a.txt", &euc), text);
+	assert_eq!(decoder.decode("/d/a.txt", &sjis), text);
+
+	let config = HashMap::from([(String::from("/d/old/"), String::from("shift_jis"))]);
+	let decoder = Decoder::new(&config).unwrap();
+	assert_eq!(decoder.decode("/d/old/a.txt", &sjis), text);
+	assert_eq!(decoder.decode_detected("/d/old/a.pdf", text.as_bytes()), text);
+	assert_eq!(decoder.lookup("/d/older/a.txt"), None);
+
+	let config = HashMap::from([(String::from("/d"), String::from("klingon"))]);
+	assert!(Decoder::new(&config).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+// 読み込んだ文書。title, body, hidden が索引に入り、title, body, uri, attrs が保存される。
+#[derive(Default, Debug, PartialEq)]
+pub struct Entry {
+    pub title: String,
+    pub body: String,
+    pub hidden: String,	// 検索はするが表示しない
+    pub uri: Option<String>,
+    pub attrs: Vec<(String, String)>,	// title, uri 以外の属性
+}
+
+impl Entry {
+    // 索引に入れる文字列
+    pub fn text(&self) -> String {
+	let mut buf = String::with_capacity(self.title.len() + self.body.len() + self.hidden.len() + 2);
+	for s in [&self.title, &self.body, &self.hidden] {
+	    if !s.is_empty() {
+		buf.push_str(s);
+		buf.push('\n');
+	    }
+	}
+	buf
+    }
+}
+
+// 1行目が題名、残りが本文
+pub fn plain(buf: &str) -> Entry {
+    let (title, body) = buf.split_once('\n').unwrap_or((buf, ""));
+    Entry {
+	title: title.trim().to_string(),
+	body: body.to_string(),
+	..Default::default()
+    }
+}
@@ -0,0 +1,82 @@
+use crate::entry::{self, Entry};
+
+// Hyper Estraier の文書ドラフト形式
+//   @title=題名        属性。空行までがヘッダ
+//   %VECTOR ...        制御行。読み飛ばす
+//                      空行
+//   本文
+//   <TAB>隠しテキスト  検索はされるが表示されない
+// 先頭が @ でも % でもなければ、ヘッダの無いただのテキストとして読む。
+pub fn parse(buf: &str) -> Entry {
+    if !buf.starts_with('@') && !buf.starts_with('%') {
+	return entry::plain(buf);
+    }
+
+    let mut entry = Entry::default();
+    let mut lines = buf.lines();
+    for line in lines.by_ref() {
+	if line.trim().is_empty() {
+	    break;
+	}
+	let attr = match line.strip_prefix('@') {
+	    Some(attr) => attr,
+	    None => continue,	// % の制御行など
+	};
+	let (name, value) = match attr.split_once('=') {
+	    Some((name, value)) => (name.trim(), value.trim()),
+	    None => continue,
+	};
+	match name {
+	    "title" => entry.title = value.to_string(),
+	    "uri" => entry.uri = Some(value.to_string()),
+	    _ if name.is_empty() => {},
+	    _ => entry.attrs.push((name.to_string(), value.to_string())),
+	}
+    }
+
+    let mut body = Vec::<&str>::new();
+    let mut hidden = Vec::<&str>::new();
+    for line in lines {
+	match line.strip_prefix('\t') {
+	    Some(text) => hidden.push(text),
+	    None => body.push(line),
+	}
+    }
+    entry.body = body.join("\n");
+    entry.hidden = hidden.join("\n");
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let buf = "@uri=http://localhost/20220805p01\n\
+		   @title=東京へ\n\
+		   @cdate=2022-08-05T10:00:00+09:00\n\
+		   @mdate=2022-08-06T09:00:00+09:00\n\
+		   %VECTOR\t東京\t10\n\
+		   \n\
+		   東京駅は混んでいた。\n\
+		   \t丸の内 八重洲\n\
+		   帰った。\n";
+	let entry = parse(buf);
+	assert_eq!(entry.title, "東京へ");
+	assert_eq!(entry.uri.as_deref(), Some("http://localhost/20220805p01"));
+	assert_eq!(entry.attrs, vec![
+	    (String::from("cdate"), String::from("2022-08-05T10:00:00+09:00")),
+	    (String::from("mdate"), String::from("2022-08-06T09:00:00+09:00")),
+	]);
+	assert_eq!(entry.body, "東京駅は混んでいた。\n帰った。");
+	assert_eq!(entry.hidden, "丸の内 八重洲");
+	assert_eq!(entry.text(), "東京へ\n東京駅は混んでいた。\n帰った。\n丸の内 八重洲\n");
+
+	// ヘッダが無ければ1行目が題名
+	let entry = parse("東京へ\n東京駅は混んでいた。\n");
+	assert_eq!(entry.title, "東京へ");
+	assert_eq!(entry.body, "東京駅は混んでいた。\n");
+	assert!(entry.attrs.is_empty());
+    }
+}
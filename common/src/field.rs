@@ -0,0 +1,45 @@
+// 索引の index.docs.txt の欄。タブと改行を含められるように \ でエスケープする。
+pub fn escape(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    for c in s.chars() {
+	match c {
+	    '\\' => buf.push_str("\\\\"),
+	    '\t' => buf.push_str("\\t"),
+	    '\n' => buf.push_str("\\n"),
+	    '\r' => {},
+	    _ => buf.push(c),
+	}
+    }
+    buf
+}
+
+pub fn unescape(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+	if c != '\\' {
+	    buf.push(c);
+	    continue;
+	}
+	match chars.next() {
+	    Some('t') => buf.push('\t'),
+	    Some('n') => buf.push('\n'),
+	    Some(c) => buf.push(c),
+	    None => {},
+	}
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let s = "題名\tタブ\n改行\\円";
+	assert_eq!(escape(s), "題名\\tタブ\\n改行\\\\円");
+	assert_eq!(unescape(&escape(s)), s);
+	assert_eq!(escape("CR\r\n"), "CR\\n");
+    }
+}
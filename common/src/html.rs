@@ -0,0 +1,291 @@
+use crate::entry::Entry;
+
+// HTML。<title> を題名に、本文は タグを取り除いて索引に入れる。
+// script や style、ナビゲーションやサイドバーの類は捨てる。
+// <link rel="canonical"> か og:url があれば、その URL を結果のリンク先にする。
+
+// 中身ごと捨てる要素
+// <head> は <title> を読むので捨てない。中の script や style はそれぞれ捨てる。
+const SKIP_TAGS: [&str; 9] = ["script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form"];
+// class や id にこれらを含む要素も捨てる (tDiary, hns の書き出しで使われているもの)
+const SKIP_CLASSES: [&str; 9] = ["sidebar", "menu", "navi", "footer", "header", "adminmenu", "comment", "referer", "calendar"];
+// 閉じタグの無い要素
+const VOID_TAGS: [&str; 14] = ["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr"];
+// 前後で改行する要素
+const BLOCK_TAGS: [&str; 24] = ["p", "div", "br", "hr", "h1", "h2", "h3", "h4", "h5", "h6", "li", "dt", "dd", "tr", "td", "th",
+				"pre", "blockquote", "section", "article", "table", "ul", "ol", "dl"];
+
+struct Tag<'a> {
+    name: String,
+    closing: bool,
+    self_closing: bool,
+    attrs: &'a str,
+}
+
+pub fn parse(buf: &str) -> Entry {
+    let mut entry = Entry::default();
+    let mut text = String::new();
+    let mut title = String::new();
+    let mut in_title = false;
+    // 捨てている要素の名前と、その中での同じ名前の要素の深さ。
+    // 中の <li> や <p> は閉じタグが無いことがあるので数えない。
+    let mut skip: Option<(String, usize)> = None;
+    let mut og_url = None;
+
+    let mut rest = buf;
+    while !rest.is_empty() {
+	let lt = match rest.find('<') {
+	    Some(lt) => lt,
+	    None => {
+		push_text(&mut text, &mut title, in_title, skip.is_some(), rest);
+		break;
+	    },
+	};
+	push_text(&mut text, &mut title, in_title, skip.is_some(), &rest[..lt]);
+	rest = &rest[lt..];
+
+	if let Some(comment) = rest.strip_prefix("<!--") {
+	    rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+	    continue;
+	}
+	let gt = match rest.find('>') {
+	    Some(gt) => gt,
+	    None => break,
+	};
+	let tag = match parse_tag(&rest[1..gt]) {
+	    Some(tag) => tag,
+	    None => {
+		rest = &rest[gt + 1..];
+		continue;	// <!DOCTYPE> など
+	    },
+	};
+	rest = &rest[gt + 1..];
+
+	match tag.name.as_str() {
+	    "title" => in_title = !tag.closing,
+	    "link" if attr(tag.attrs, "rel").is_some_and(|rel| rel.eq_ignore_ascii_case("canonical")) => {
+		entry.uri = attr(tag.attrs, "href");
+	    },
+	    "meta" if attr(tag.attrs, "property").as_deref() == Some("og:url") => {
+		og_url = attr(tag.attrs, "content");
+	    },
+	    _ => {},
+	}
+	if tag.name == "title" {
+	    continue;
+	}
+	let opens = !tag.closing && !tag.self_closing && !VOID_TAGS.contains(&tag.name.as_str());
+	match skip.as_mut() {
+	    Some((name, depth)) if *name == tag.name => {
+		if tag.closing {
+		    *depth -= 1;
+		    if *depth == 0 {
+			skip = None;
+		    }
+		} else if opens {
+		    *depth += 1;
+		}
+		continue;
+	    },
+	    Some(_) => continue,
+	    None if opens && is_boilerplate(&tag) => {
+		skip = Some((tag.name.clone(), 1));
+		continue;
+	    },
+	    None => {},
+	}
+	if BLOCK_TAGS.contains(&tag.name.as_str()) {
+	    text.push('\n');
+	}
+    }
+
+    entry.title = collapse(&title);
+    if entry.uri.is_none() {
+	entry.uri = og_url;
+    }
+    // ページに書いてあるものなので、http と https 以外は使わない
+    entry.uri = entry.uri.filter(|uri| {
+	let lower = uri.trim().to_ascii_lowercase();
+	lower.starts_with("http://") || lower.starts_with("https://")
+    });
+    entry.body = text.lines()
+	.map(collapse)
+	.filter(|line| !line.is_empty())
+	.collect::<Vec<String>>()
+	.join("\n");
+    if entry.title.is_empty() {
+	entry.title = entry.body.lines().next().unwrap_or("").to_string();
+    }
+    entry
+}
+
+fn is_boilerplate(tag: &Tag) -> bool {
+    if SKIP_TAGS.contains(&tag.name.as_str()) {
+	return true;
+    }
+    let names = [attr(tag.attrs, "class"), attr(tag.attrs, "id")];
+    names.iter().flatten().any(|value| {
+	value.split_whitespace().any(|name| {
+	    let name = name.to_ascii_lowercase();
+	    SKIP_CLASSES.iter().any(|skip| name == *skip || name.starts_with(&format!("{}-", skip)))
+	})
+    })
+}
+
+fn push_text(text: &mut String, title: &mut String, in_title: bool, skipping: bool, s: &str) {
+    if in_title {
+	title.push_str(&decode_entities(s));
+    } else if !skipping {
+	text.push_str(&decode_entities(s).replace('\n', " "));
+    }
+}
+
+fn parse_tag(s: &str) -> Option<Tag<'_>> {
+    let (closing, s) = match s.strip_prefix('/') {
+	Some(s) => (true, s),
+	None => (false, s),
+    };
+    let end = s.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(s.len());
+    let name = &s[..end];
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+	return None;
+    }
+    Some(Tag {
+	name: name.to_ascii_lowercase(),
+	closing,
+	self_closing: s.ends_with('/'),
+	attrs: &s[end..],
+    })
+}
+
+// name="value"、name='value'、name=value
+fn attr(attrs: &str, name: &str) -> Option<String> {
+    let lower = attrs.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(name) {
+	let start = from + pos;
+	from = start + name.len();
+	let before_ok = start == 0 || lower[..start].ends_with(|c: char| c.is_whitespace());
+	let after = lower[from..].trim_start();
+	if !before_ok || !after.starts_with('=') {
+	    continue;
+	}
+	let value = attrs[attrs.len() - after.len() + 1..].trim_start();
+	let value = match value.chars().next() {
+	    Some(q @ ('"' | '\'')) => value[1..].split(q).next().unwrap_or(""),
+	    _ => value.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or(""),
+	};
+	return Some(decode_entities(value));
+    }
+    None
+}
+
+pub fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+	return s.to_string();
+    }
+    let mut buf = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+	buf.push_str(&rest[..amp]);
+	rest = &rest[amp..];
+	let end = match rest[1..].find(|c: char| !c.is_ascii_alphanumeric() && c != '#').map(|i| i + 1) {
+	    Some(end) if end > 1 => end,
+	    None if rest.len() > 1 => rest.len(),
+	    _ => {
+		buf.push('&');
+		rest = &rest[1..];
+		continue;
+	    },
+	};
+	let name = &rest[1..end];
+	let c = match name {
+	    "amp" => Some('&'),
+	    "lt" => Some('<'),
+	    "gt" => Some('>'),
+	    "quot" => Some('"'),
+	    "apos" => Some('\''),
+	    "nbsp" => Some(' '),
+	    "copy" => Some('©'),
+	    "hellip" => Some('…'),
+	    "mdash" => Some('—'),
+	    "ndash" => Some('–'),
+	    _ => {
+		let code = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+		    u32::from_str_radix(hex, 16).ok()
+		} else {
+		    name.strip_prefix('#').and_then(|dec| dec.parse().ok())
+		};
+		code.and_then(char::from_u32)
+	    },
+	};
+	match c {
+	    Some(c) => {
+		buf.push(c);
+		rest = &rest[end..];
+		rest = rest.strip_prefix(';').unwrap_or(rest);
+	    },
+	    None => {
+		buf.push('&');
+		rest = &rest[1..];
+	    },
+	}
+    }
+    buf.push_str(rest);
+    buf
+}
+
+// 連続する空白を一つにする
+fn collapse(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let buf = r#"<!DOCTYPE html>
+<html><head>
+<meta charset="utf-8">
+<title>日記 (2022-08-05) &amp; メモ</title>
+<link rel="stylesheet" href="/style.css">
+<link rel="canonical" href="https://example.com/?date=20220805">
+<script>var x = "<p>東京</p>";</script>
+</head>
+<body>
+<div class="adminmenu"><a href="/">最新</a> <a href="/update.rb">追記</a></div>
+<nav>前の日 | 次の日</nav>
+<div class="day">
+<h2><span class="date">2022-08-05</span> 東京へ</h2>
+<div class="section"><p>東京駅は&lt;混んで&gt;いた。<br>
+帰った&#x3002;&#12290;</p>
+<!-- <p>コメントアウト</p> -->
+</div>
+<div class="comment">通りすがり: よかったですね</div>
+</div>
+<div class="footer">Generated by tDiary</div>
+</body></html>
+"#;
+	let entry = parse(buf);
+	assert_eq!(entry.title, "日記 (2022-08-05) & メモ");
+	assert_eq!(entry.uri.as_deref(), Some("https://example.com/?date=20220805"));
+	assert_eq!(entry.body, "2022-08-05 東京へ\n東京駅は<混んで>いた。\n帰った。。");
+
+	let entry = parse("<meta property=\"og:url\" content=\"https://example.com/a\"><p>題名なし</p>");
+	assert_eq!(entry.uri.as_deref(), Some("https://example.com/a"));
+	assert_eq!(entry.title, "題名なし");
+
+	let entry = parse("<link rel=\"canonical\" href=\"javascript:alert(1)\"><p>本文です</p>");
+	assert_eq!(entry.uri, None);
+
+	// 閉じていない <li> があっても、<nav> の後は読む
+	let entry = parse("<nav><ul><li>prev<li>next</ul></nav><div class=\"day\"><p>本文です</p></div>");
+	assert_eq!(entry.body, "本文です");
+	let entry = parse("<div class=\"sidebar\"><div>カレンダー</div><p>リンク</div><p>本文です");
+	assert_eq!(entry.body, "本文です");
+
+	assert_eq!(decode_entities("a &amp b &unknown; &#60;"), "a & b &unknown; <");
+    }
+}
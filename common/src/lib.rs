@@ -0,0 +1,13 @@
+// rsdiary_add と rsdiary_search の両方で使うもの
+pub mod config;
+pub mod decode;
+pub mod entry;
+pub mod est;
+pub mod field;
+pub mod html;
+pub mod markdown;
+pub mod mime;
+pub mod org;
+pub mod reader;
+pub mod section;
+pub mod tdiary;
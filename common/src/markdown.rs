@@ -0,0 +1,206 @@
+use pulldown_cmark::{Event, Parser, Tag};
+use crate::entry::Entry;
+
+// Markdown。先頭に front matter があれば属性として読む。
+//   ---              +++
+//   title: 東京へ    title = "東京へ"
+//   date: 2022-08-05 date = 2022-08-05
+//   tags: [旅行]     tags = ["旅行"]
+//   ---              +++
+// 記法は取り除き、見出しと本文の文字だけを索引に入れる。
+pub fn parse(buf: &str) -> Entry {
+    let (attrs, text) = split_front_matter(buf);
+    let (body, heading) = to_plain(text);
+
+    let mut entry = Entry::default();
+    for (name, value) in attrs {
+	match name.as_str() {
+	    "title" => entry.title = value,
+	    "url" | "uri" => entry.uri = Some(value),
+	    "tags" | "categories" => {
+		// タグは検索できるようにするが本文には出さない
+		if !entry.hidden.is_empty() {
+		    entry.hidden.push('\n');
+		}
+		entry.hidden.push_str(&value);
+		entry.attrs.push((name, value));
+	    },
+	    _ => entry.attrs.push((name, value)),
+	}
+    }
+    if entry.title.is_empty() {
+	entry.title = heading
+	    .or_else(|| body.lines().find(|line| !line.trim().is_empty()).map(|line| line.trim().to_string()))
+	    .unwrap_or_default();
+    }
+    entry.body = body;
+    entry
+}
+
+// front matter を (名前, 値) の並びにして、残りの本文と一緒に返す
+fn split_front_matter(buf: &str) -> (Vec<(String, String)>, &str) {
+    let buf = buf.strip_prefix('\u{feff}').unwrap_or(buf);
+    let (delim, yaml) = if buf.starts_with("---\n") || buf.starts_with("---\r\n") {
+	("---", true)
+    } else if buf.starts_with("+++\n") || buf.starts_with("+++\r\n") {
+	("+++", false)
+    } else {
+	return (vec![], buf);
+    };
+    let start = buf.find('\n').unwrap() + 1;
+    let mut pos = start;
+    let end = loop {
+	let line_end = buf[pos..].find('\n').map_or(buf.len(), |i| pos + i + 1);
+	let line = buf[pos..line_end].trim_end();
+	if line == delim || (yaml && line == "...") {
+	    break Some((pos, line_end));
+	}
+	if line_end >= buf.len() {
+	    break None;
+	}
+	pos = line_end;
+    };
+    let (end, rest) = match end {
+	Some(end) => end,
+	None => return (vec![], buf),	// 閉じていなければただの本文
+    };
+    let header = &buf[start..end];
+    let attrs = if yaml { yaml_attrs(header) } else { toml_attrs(header) };
+    (attrs, &buf[rest..])
+}
+
+fn yaml_attrs(header: &str) -> Vec<(String, String)> {
+    let map = match serde_yaml::from_str::<serde_yaml::Value>(header) {
+	Ok(serde_yaml::Value::Mapping(map)) => map,
+	Ok(_) => return vec![],
+	Err(why) => {
+	    eprintln!("couldn't parse front matter: {}", why);
+	    return vec![];
+	},
+    };
+    map.iter().filter_map(|(k, v)| {
+	let name = k.as_str()?.to_string();
+	let value = match v {
+	    serde_yaml::Value::Sequence(list) => list.iter().filter_map(yaml_scalar).collect::<Vec<String>>().join(", "),
+	    v => yaml_scalar(v)?,
+	};
+	Some((name, value))
+    }).collect()
+}
+
+fn yaml_scalar(v: &serde_yaml::Value) -> Option<String> {
+    match v {
+	serde_yaml::Value::String(s) => Some(s.clone()),
+	serde_yaml::Value::Number(n) => Some(n.to_string()),
+	serde_yaml::Value::Bool(b) => Some(b.to_string()),
+	_ => None,
+    }
+}
+
+fn toml_attrs(header: &str) -> Vec<(String, String)> {
+    let table = match header.parse::<toml::Value>() {
+	Ok(toml::Value::Table(table)) => table,
+	Ok(_) => return vec![],
+	Err(why) => {
+	    eprintln!("couldn't parse front matter: {}", why);
+	    return vec![];
+	},
+    };
+    table.iter().filter_map(|(name, v)| {
+	let value = match v {
+	    toml::Value::Array(list) => list.iter().filter_map(toml_scalar).collect::<Vec<String>>().join(", "),
+	    v => toml_scalar(v)?,
+	};
+	Some((name.clone(), value))
+    }).collect()
+}
+
+fn toml_scalar(v: &toml::Value) -> Option<String> {
+    match v {
+	toml::Value::String(s) => Some(s.clone()),
+	toml::Value::Integer(n) => Some(n.to_string()),
+	toml::Value::Float(n) => Some(n.to_string()),
+	toml::Value::Boolean(b) => Some(b.to_string()),
+	toml::Value::Datetime(d) => Some(d.to_string()),
+	_ => None,
+    }
+}
+
+// 記法を取り除いた文字列と、最初の見出し
+fn to_plain(text: &str) -> (String, Option<String>) {
+    let mut buf = String::new();
+    let mut heading: Option<String> = None;
+    let mut in_heading = false;
+    let mut heading_text = String::new();
+    for event in Parser::new(text) {
+	match event {
+	    Event::Text(s) | Event::Code(s) => {
+		buf.push_str(&s);
+		if in_heading {
+		    heading_text.push_str(&s);
+		}
+	    },
+	    Event::SoftBreak | Event::HardBreak | Event::Rule => buf.push('\n'),
+	    Event::Start(Tag::Heading(..)) => {
+		in_heading = true;
+		heading_text.clear();
+	    },
+	    Event::End(Tag::Heading(..)) => {
+		in_heading = false;
+		if heading.is_none() && !heading_text.trim().is_empty() {
+		    heading = Some(heading_text.trim().to_string());
+		}
+		buf.push('\n');
+	    },
+	    Event::End(Tag::Paragraph) | Event::End(Tag::Item) | Event::End(Tag::CodeBlock(_))
+		| Event::End(Tag::TableRow) | Event::End(Tag::TableHead) => buf.push('\n'),
+	    Event::End(Tag::TableCell) => buf.push(' '),
+	    _ => {},	// HTML やリンク先の URL は捨てる
+	}
+    }
+    let buf = buf.lines().map(|line| line.trim_end()).collect::<Vec<&str>>().join("\n");
+    (buf.trim().to_string(), heading)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let buf = "---\n\
+		   title: 東京へ\n\
+		   date: 2022-08-05\n\
+		   tags: [旅行, 東京]\n\
+		   ---\n\
+		   # 朝\n\
+		   \n\
+		   **東京駅**は[混んで](http://example.com/)いた。\n\
+		   \n\
+		   - 丸の内\n\
+		   - `八重洲`\n";
+	let entry = parse(buf);
+	assert_eq!(entry.title, "東京へ");
+	assert_eq!(entry.attrs, vec![
+	    (String::from("date"), String::from("2022-08-05")),
+	    (String::from("tags"), String::from("旅行, 東京")),
+	]);
+	assert_eq!(entry.hidden, "旅行, 東京");
+	assert_eq!(entry.body, "朝\n東京駅は混んでいた。\n丸の内\n八重洲");
+
+	let buf = "+++\ntitle = \"東京へ\"\ndate = 2022-08-05\n+++\n本文\n";
+	let entry = parse(buf);
+	assert_eq!(entry.title, "東京へ");
+	assert_eq!(entry.attrs, vec![(String::from("date"), String::from("2022-08-05"))]);
+	assert_eq!(entry.body, "本文");
+
+	// front matter が無ければ最初の見出しが題名
+	let entry = parse("前置き\n\n## 8月5日\n\n晴れ。\n");
+	assert_eq!(entry.title, "8月5日");
+	assert_eq!(entry.body, "前置き\n8月5日\n晴れ。");
+
+	// 閉じていない --- は本文
+	let entry = parse("---\n題名\n");
+	assert_eq!(entry.title, "題名");
+    }
+}
@@ -0,0 +1,278 @@
+use encoding_rs::Encoding;
+
+// メールの MIME。rsdiary_add の索引作りと rsdiary_search の表示で同じ読み方をする。
+pub struct Message {
+    pub headers: Vec<(String, String)>,	// 名前は小文字
+    pub parts: Vec<(String, String)>,	// (text/plain などの型, 本文)
+}
+
+impl Message {
+    // mbox なら "From " 行を除いた1通分、Maildir なら1ファイル
+    pub fn parse(raw: &[u8]) -> Self {
+	let (headers, body) = split_header(raw);
+	let mut parts = Vec::<(String, String)>::new();
+	collect_parts(&headers, body, &mut parts, 0);
+	Message { headers, parts }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+	self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    // RFC 2047 を戻した値
+    pub fn decoded_header(&self, name: &str) -> Option<String> {
+	self.header(name).map(decode_words)
+    }
+
+    pub fn part(&self, mime: &str) -> Option<&str> {
+	self.parts.iter().find(|(m, _)| m == mime).map(|(_, text)| text.as_str())
+    }
+}
+
+pub fn trim_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+// mboxrd の ">From " を戻してつなぐ
+pub fn unescape_mbox(lines: &[&[u8]]) -> Vec<u8> {
+    lines.iter()
+	.map(|line| {
+	    let quoted = line.iter().take_while(|b| **b == b'>').count();
+	    if quoted > 0 && line[quoted..].starts_with(b"From ") { &line[1..] } else { line }
+	})
+	.collect::<Vec<&[u8]>>()
+	.join(&b'\n')
+}
+
+// ヘッダ (名前は小文字に、折り返しはつなぐ) と本文に分ける
+fn split_header(raw: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let mut headers = Vec::<(String, String)>::new();
+    let mut pos = 0;
+    while pos < raw.len() {
+	let end = raw[pos..].iter().position(|b| *b == b'\n').map_or(raw.len(), |i| pos + i);
+	let line = String::from_utf8_lossy(trim_cr(&raw[pos..end])).into_owned();
+	pos = (end + 1).min(raw.len());
+	if line.is_empty() {
+	    break;
+	}
+	if line.starts_with([' ', '\t']) {
+	    if let Some((_, value)) = headers.last_mut() {
+		value.push(' ');
+		value.push_str(line.trim());
+	    }
+	} else if let Some((name, value)) = line.split_once(':') {
+	    headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+	}
+    }
+    (headers, &raw[pos..])
+}
+
+fn collect_parts(headers: &[(String, String)], body: &[u8], parts: &mut Vec<(String, String)>, depth: usize) {
+    let content_type = headers.iter()
+	.find(|(n, _)| n == "content-type")
+	.map_or("text/plain", |(_, v)| v.as_str());
+    let (mime, params) = parse_content_type(content_type);
+    if mime.starts_with("multipart/") {
+	let boundary = match params.iter().find(|(n, _)| n == "boundary") {
+	    Some((_, boundary)) if depth < 8 => format!("--{}", boundary),
+	    _ => return,
+	};
+	for part in split_multipart(body, boundary.as_bytes()) {
+	    let (headers, body) = split_header(part);
+	    collect_parts(&headers, body, parts, depth + 1);
+	}
+	return;
+    }
+    if mime != "text/plain" && mime != "text/html" {
+	return;
+    }
+    let encoding = headers.iter()
+	.find(|(n, _)| n == "content-transfer-encoding")
+	.map_or(String::new(), |(_, v)| v.trim().to_ascii_lowercase());
+    let bytes = match encoding.as_str() {
+	"base64" => decode_base64(body),
+	"quoted-printable" => decode_qp(body, false),
+	_ => body.to_vec(),
+    };
+    let charset = params.iter().find(|(n, _)| n == "charset").map_or("utf-8", |(_, v)| v.as_str());
+    parts.push((mime, decode_charset(charset, &bytes)));
+}
+
+// text/plain; charset="ISO-2022-JP" → ("text/plain", [("charset", "ISO-2022-JP")])
+fn parse_content_type(value: &str) -> (String, Vec<(String, String)>) {
+    let mut iter = value.split(';');
+    let mime = iter.next().unwrap_or("").trim().to_ascii_lowercase();
+    let params = iter
+	.filter_map(|param| {
+	    let (name, value) = param.split_once('=')?;
+	    Some((name.trim().to_ascii_lowercase(), value.trim().trim_matches('"').to_string()))
+	})
+	.collect();
+    (mime, params)
+}
+
+fn split_multipart<'a>(body: &'a [u8], boundary: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = Vec::<&[u8]>::new();
+    let mut start = None;
+    let mut pos = 0;
+    while pos < body.len() {
+	let end = body[pos..].iter().position(|b| *b == b'\n').map_or(body.len(), |i| pos + i);
+	let line = trim_cr(&body[pos..end]);
+	if line.starts_with(boundary) {
+	    if let Some(start) = start {
+		parts.push(&body[start..pos.saturating_sub(1).max(start)]);
+	    }
+	    if line[boundary.len()..].starts_with(b"--") {
+		return parts;
+	    }
+	    start = Some((end + 1).min(body.len()));
+	}
+	pos = end + 1;
+    }
+    if let Some(start) = start {
+	parts.push(&body[start..]);
+    }
+    parts
+}
+
+// RFC 2047 の =?ISO-2022-JP?B?...?=。並んだ encoded-word の間の空白は捨てる。
+pub fn decode_words(value: &str) -> String {
+    let mut buf = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some((start, end, text)) = find_word(rest) {
+	let before = &rest[..start];
+	if !(after_word && before.trim().is_empty()) {
+	    buf.push_str(before);
+	}
+	buf.push_str(&text);
+	after_word = true;
+	rest = &rest[end..];
+    }
+    buf.push_str(rest);
+    buf.trim().to_string()
+}
+
+// (始まり, 終わり, 戻した文字列)
+fn find_word(s: &str) -> Option<(usize, usize, String)> {
+    let mut from = 0;
+    while let Some(i) = s[from..].find("=?") {
+	let start = from + i;
+	from = start + 2;
+	let mut iter = s[from..].splitn(3, '?');
+	let (charset, enc, rest) = match (iter.next(), iter.next(), iter.next()) {
+	    (Some(charset), Some(enc), Some(rest)) => (charset, enc, rest),
+	    _ => return None,
+	};
+	let text = match rest.find("?=") {
+	    Some(pos) => &rest[..pos],
+	    None => return None,
+	};
+	if charset.is_empty() || charset.contains(char::is_whitespace) || text.contains(char::is_whitespace) {
+	    continue;
+	}
+	let bytes = match enc {
+	    "B" | "b" => decode_base64(text.as_bytes()),
+	    "Q" | "q" => decode_qp(text.as_bytes(), true),
+	    _ => continue,
+	};
+	let end = from + charset.len() + 1 + enc.len() + 1 + text.len() + 2;
+	let charset = charset.split('*').next().unwrap_or("");
+	return Some((start, end, decode_charset(charset, &bytes)));
+    }
+    None
+}
+
+fn decode_base64(s: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::<u8>::with_capacity(s.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut n = 0;
+    for c in s.iter() {
+	let v = match c {
+	    b'A'..=b'Z' => c - b'A',
+	    b'a'..=b'z' => c - b'a' + 26,
+	    b'0'..=b'9' => c - b'0' + 52,
+	    b'+' => 62,
+	    b'/' => 63,
+	    b'=' => break,
+	    _ => continue,
+	};
+	bits = bits << 6 | v as u32;
+	n += 6;
+	if n >= 8 {
+	    n -= 8;
+	    buf.push((bits >> n) as u8);
+	}
+    }
+    buf
+}
+
+// header が true なら RFC 2047 の Q エンコーディング (_ が空白)
+fn decode_qp(s: &[u8], header: bool) -> Vec<u8> {
+    let mut buf = Vec::<u8>::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+	match s[i] {
+	    b'=' => {
+		let rest = &s[i + 1..];
+		if rest.starts_with(b"\r\n") {
+		    i += 3;
+		    continue;
+		}
+		if rest.starts_with(b"\n") {
+		    i += 2;
+		    continue;
+		}
+		let hex = rest.get(..2).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+		match hex {
+		    Some(b) => {
+			buf.push(b);
+			i += 3;
+			continue;
+		    },
+		    None => buf.push(b'='),
+		}
+	    },
+	    b'_' if header => buf.push(b' '),
+	    b => buf.push(b),
+	}
+	i += 1;
+    }
+    buf
+}
+
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    let encoding = Encoding::for_label(charset.trim().as_bytes()).unwrap_or(encoding_rs::UTF_8);
+    let (text, _) = encoding.decode_without_bom_handling(bytes);
+    text.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	assert_eq!(decode_words("=?ISO-2022-JP?B?GyRCRWw1fiRYGyhC?= =?UTF-8?Q?=E3=81=BE=E3=81=95?= さん"), "東京へまさ さん");
+	assert_eq!(decode_words("=?bad =?UTF-8?q?a_b?="), "=?bad a b");
+
+	let raw = b"Subject: =?UTF-8?B?5p2x5Lqs?=\r\n\
+		    Content-Type: multipart/mixed; boundary=b1\r\n\
+		    \r\n\
+		    --b1\r\n\
+		    Content-Type: text/plain; charset=iso-2022-jp\r\n\
+		    Content-Transfer-Encoding: base64\r\n\
+		    \r\n\
+		    GyRCRWw1fjFYJE86LiRzJEckJCQ/ISMbKEIK\r\n\
+		    --b1\r\n\
+		    Content-Type: image/png\r\n\
+		    \r\n\
+		    xxx\r\n\
+		    --b1--\r\n";
+	let message = Message::parse(raw);
+	assert_eq!(message.decoded_header("subject").as_deref(), Some("東京"));
+	assert_eq!(message.part("text/plain"), Some("東京駅は混んでいた。\n"));
+	assert_eq!(message.part("text/html"), None);
+	assert_eq!(message.parts.len(), 1);
+    }
+}
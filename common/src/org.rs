@@ -0,0 +1,132 @@
+use regex::Regex;
+use crate::entry::Entry;
+
+// Org mode。#+TITLE: などのキーワードは属性に、見出しや本文は記法を取り除いて索引に入れる。
+// プロパティドロワーと SCHEDULED: などの行は捨てる。
+pub struct Parser {
+    link: Regex,
+    markup: Regex,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+	Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+	Parser {
+	    link: Regex::new(r"\[\[([^\]]*)\](?:\[([^\]]*)\])?\]").unwrap(),
+	    markup: Regex::new(r"(^|[\s(])([*=~+/])(\S|\S.*?\S)([*=~+/])($|[\s).,、。])").unwrap(),
+	}
+    }
+
+    pub fn parse(&self, buf: &str) -> Entry {
+	let mut entry = Entry::default();
+	let mut body = Vec::<String>::new();
+	let mut heading: Option<String> = None;
+	let mut in_drawer = false;
+	for line in buf.lines() {
+	    let trimmed = line.trim();
+	    if in_drawer {
+		in_drawer = !trimmed.eq_ignore_ascii_case(":END:");
+		continue;
+	    }
+	    if trimmed.starts_with(':') && trimmed.ends_with(':') && trimmed.len() > 2 && !trimmed.contains(' ') {
+		in_drawer = true;	// :PROPERTIES: や :LOGBOOK:
+		continue;
+	    }
+	    if let Some(keyword) = trimmed.strip_prefix("#+") {
+		if let Some((name, value)) = keyword.split_once(':') {
+		    let name = name.to_ascii_lowercase();
+		    let value = value.trim().to_string();
+		    match name.as_str() {
+			"title" => entry.title = value,
+			_ if name.starts_with("begin_") || name.starts_with("end_") => {},
+			"options" | "startup" | "setupfile" => {},
+			_ if !value.is_empty() => entry.attrs.push((name, value)),
+			_ => {},
+		    }
+		}
+		continue;	// #+BEGIN_SRC なども捨てて中身だけ残す
+	    }
+	    if trimmed == "#" || trimmed.starts_with("# ") {
+		continue;	// コメント
+	    }
+	    if ["SCHEDULED:", "DEADLINE:", "CLOSED:"].iter().any(|k| trimmed.starts_with(k)) {
+		continue;
+	    }
+	    let text = match heading_text(line) {
+		Some(text) => {
+		    if heading.is_none() && !text.is_empty() {
+			heading = Some(text.to_string());
+		    }
+		    text
+		},
+		None => line,
+	    };
+	    let text = self.link.replace_all(text, |caps: &regex::Captures| {
+		caps.get(2).or_else(|| caps.get(1)).map_or("", |m| m.as_str()).to_string()
+	    });
+	    let text = self.markup.replace_all(&text, "$1$3$5");
+	    body.push(text.trim_end().to_string());
+	}
+
+	entry.body = body.join("\n").trim().to_string();
+	if entry.title.is_empty() {
+	    entry.title = heading
+		.or_else(|| entry.body.lines().find(|line| !line.trim().is_empty()).map(|line| line.trim().to_string()))
+		.unwrap_or_default();
+	}
+	entry
+    }
+}
+
+// 見出しなら星と末尾のタグを除いた文字列
+pub fn heading_text(line: &str) -> Option<&str> {
+    let rest = line.trim_start_matches('*');
+    if rest.len() == line.len() || !rest.starts_with(' ') {
+	return None;
+    }
+    let rest = rest.trim();
+    // 末尾の :tag1:tag2:
+    let rest = match rest.rsplit_once(char::is_whitespace) {
+	Some((head, tags)) if tags.len() > 2 && tags.starts_with(':') && tags.ends_with(':') => head.trim_end(),
+	_ => rest,
+    };
+    Some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let buf = "#+TITLE: 2022年8月\n\
+		   #+AUTHOR: masm\n\
+		   #+STARTUP: overview\n\
+		   \n\
+		   * 2022-08-05 東京へ :旅行:\n\
+		   :PROPERTIES:\n\
+		   :ID: abc\n\
+		   :END:\n\
+		   SCHEDULED: <2022-08-05 Fri>\n\
+		   *東京駅* は [[https://example.com/][混んで]] いた。\n\
+		   # メモ\n\
+		   #+BEGIN_SRC sh\n\
+		   ls ~/diary\n\
+		   #+END_SRC\n";
+	let parser = Parser::new();
+	let entry = parser.parse(buf);
+	assert_eq!(entry.title, "2022年8月");
+	assert_eq!(entry.attrs, vec![(String::from("author"), String::from("masm"))]);
+	assert_eq!(entry.body, "2022-08-05 東京へ\n東京駅 は 混んで いた。\nls ~/diary");
+
+	let entry = parser.parse("* 8月5日\n晴れ。\n");
+	assert_eq!(entry.title, "8月5日");
+	assert_eq!(heading_text("** TODO 買い物 :home:"), Some("TODO 買い物"));
+	assert_eq!(heading_text("*強調* の行"), None);
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::path::Path;
+use crate::entry::{self, Entry};
+use crate::est;
+use crate::html;
+use crate::markdown;
+use crate::org;
+use crate::tdiary;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Plain,
+    Est,
+    Markdown,
+    Org,
+    Html,
+    Tdiary,
+}
+
+impl Format {
+    fn from_name(name: &str) -> Option<Self> {
+	match name {
+	    "plain" => Some(Format::Plain),
+	    "est" => Some(Format::Est),
+	    "markdown" => Some(Format::Markdown),
+	    "org" => Some(Format::Org),
+	    "html" => Some(Format::Html),
+	    "tdiary" => Some(Format::Tdiary),
+	    _ => None,
+	}
+    }
+}
+
+// 拡張子と読み方の対応。設定の [formats] で追加、変更できる。
+pub struct Formats {
+    map: HashMap<String, Format>,
+    org: org::Parser,
+}
+
+impl Formats {
+    pub fn new(config: &HashMap<String, String>) -> Result<Self, String> {
+	let mut map = HashMap::<String, Format>::new();
+	map.insert(String::from("est"), Format::Est);
+	map.insert(String::from("md"), Format::Markdown);
+	map.insert(String::from("markdown"), Format::Markdown);
+	map.insert(String::from("org"), Format::Org);
+	map.insert(String::from("html"), Format::Html);
+	map.insert(String::from("htm"), Format::Html);
+	map.insert(String::from("td2"), Format::Tdiary);
+	for (ext, name) in config.iter() {
+	    let format = Format::from_name(name)
+		.ok_or(format!("unknown format for .{}: {}", ext, name))?;
+	    map.insert(ext.trim_start_matches('.').to_string(), format);
+	}
+	Ok(Formats { map, org: org::Parser::new() })
+    }
+
+    pub fn get(&self, path: &str) -> Format {
+	let ext = Path::new(path).extension().and_then(|s| s.to_str()).unwrap_or("");
+	self.map.get(ext).cloned().unwrap_or(Format::Plain)
+    }
+
+    pub fn read(&self, path: &str, buf: &str) -> Entry {
+	match self.get(path) {
+	    Format::Plain => entry::plain(buf),
+	    Format::Est => est::parse(buf),
+	    Format::Markdown => markdown::parse(buf),
+	    Format::Org => self.org.parse(buf),
+	    Format::Html => html::parse(buf),
+	    Format::Tdiary => tdiary::parse(buf),
+	}
+    }
+
+    // 見出しで分けた文書の、見出しの次の行から後ろ。style は tDiary の Format: の値。
+    pub fn read_part(&self, format: Format, buf: &str, style: Option<&str>) -> Entry {
+	match format {
+	    Format::Markdown => markdown::parse(buf),
+	    Format::Org => self.org.parse(buf),
+	    Format::Tdiary => tdiary::parse_day(buf, style.unwrap_or("tdiary")),
+	    _ => Entry { body: buf.to_string(), ..Default::default() },
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let formats = Formats::new(&HashMap::new()).unwrap();
+	assert_eq!(formats.get("/diary/20220805p01.est"), Format::Est);
+	assert_eq!(formats.get("/diary/2022-08-05.md"), Format::Markdown);
+	assert_eq!(formats.get("/diary/2022-08.org"), Format::Org);
+	assert_eq!(formats.get("/www/20220805.html"), Format::Html);
+	assert_eq!(formats.get("/tdiary/2022/202208.td2"), Format::Tdiary);
+	assert_eq!(formats.get("/diary/memo.txt"), Format::Plain);
+	assert_eq!(formats.get("/diary/README"), Format::Plain);
+
+	let config = HashMap::from([(String::from(".txt"), String::from("markdown"))]);
+	let formats = Formats::new(&config).unwrap();
+	assert_eq!(formats.get("/diary/memo.txt"), Format::Markdown);
+
+	let config = HashMap::from([(String::from("txt"), String::from("word"))]);
+	assert!(Formats::new(&config).is_err());
+    }
+}
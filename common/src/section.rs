@@ -0,0 +1,223 @@
+use regex::Regex;
+use crate::entry::Entry;
+use crate::markdown;
+use crate::org;
+use crate::reader::Format;
+
+// 1か月分を1ファイルに書いた日記を、日付の見出しごとの文書に分ける。
+//   * 2022-08-05          (Org)
+//   ## 8月5日             (Markdown。年は前の見出しかファイルの日付から)
+// 最初に日付のあった見出しの深さで分け、それより深い見出しは中身として扱う。
+// 最初の日付の見出しより前は、ファイル自身の文書にする。
+pub struct Section {
+    pub anchor: String,	// 索引には path#anchor で登録する
+    pub date: Option<u32>,
+    pub lines: (usize, usize),	// 見出しから最後の行まで。1 始まり
+    pub entry: Entry,
+}
+
+struct Heading {
+    line: usize,	// 0 始まり
+    level: usize,
+    text: String,
+}
+
+pub struct Splitter {
+    full: Regex,
+    month_day: Regex,
+    year: Regex,
+    org: org::Parser,
+}
+
+impl Default for Splitter {
+    fn default() -> Self {
+	Self::new()
+    }
+}
+
+impl Splitter {
+    pub fn new() -> Self {
+	Splitter {
+	    full: Regex::new(r"(?P<y>\d{4})[-/.年](?P<m>\d{1,2})[-/.月](?P<d>\d{1,2})").unwrap(),
+	    month_day: Regex::new(r"(?P<m>\d{1,2})月(?P<d>\d{1,2})日").unwrap(),
+	    // 「2000円」などを年と取らないよう、年は「年」の付いたものだけ
+	    year: Regex::new(r"(?P<y>\d{4})年").unwrap(),
+	    org: org::Parser::new(),
+	}
+    }
+
+    pub fn can_split(format: Format) -> bool {
+	matches!(format, Format::Org | Format::Markdown)
+    }
+
+    // 日付の見出しが一つも無ければ None
+    pub fn split(&self, buf: &str, format: Format, file_date: Option<u32>) -> Option<(Entry, Vec<Section>)> {
+	let lines: Vec<&str> = buf.lines().collect();
+	let headings = Self::headings(&lines, format);
+
+	let mut year = file_date.map(|d| d / 10000);
+	let mut level = None;
+	let mut starts = Vec::<(usize, Option<u32>, String)>::new();	// (見出しの位置, 日付, 見出し)
+	for h in headings.iter() {
+	    if let Some(caps) = self.full.captures(&h.text) {
+		year = caps["y"].parse().ok();
+	    } else if let Some(caps) = self.year.captures(&h.text) {
+		year = caps["y"].parse().ok();
+	    }
+	    let day = self.day(&h.text, year);
+	    match (level, day) {
+		(None, Some(date)) => {
+		    level = Some(h.level);
+		    starts.push((h.line, date, h.text.clone()));
+		},
+		(Some(l), Some(date)) if h.level == l => starts.push((h.line, date, h.text.clone())),
+		_ => {},
+	    }
+	}
+	if starts.is_empty() {
+	    return None;
+	}
+
+	let preamble = self.read(&lines[..starts[0].0].join("\n"), format);
+	let mut sections = Vec::<Section>::new();
+	for (i, (start, date, heading)) in starts.iter().enumerate() {
+	    let end = starts.get(i + 1).map_or(lines.len(), |next| next.0);
+	    let mut entry = self.read(&lines[start + 1..end].join("\n"), format);
+	    entry.title = heading.clone();
+	    let mut anchor = match date {
+		Some(d) => format!("{:04}-{:02}-{:02}", d / 10000, d / 100 % 100, d % 100),
+		None => format!("p{}", start + 1),
+	    };
+	    if sections.iter().any(|s| s.anchor == anchor) {
+		anchor = format!("{}-{}", anchor, start + 1);
+	    }
+	    sections.push(Section {
+		anchor,
+		date: *date,
+		lines: (start + 1, end),
+		entry,
+	    });
+	}
+	Some((preamble, sections))
+    }
+
+    // 日付の見出しなら Some(日付)。年が分からなければ Some(None)。
+    fn day(&self, text: &str, year: Option<u32>) -> Option<Option<u32>> {
+	let (y, m, d) = if let Some(caps) = self.full.captures(text) {
+	    (caps["y"].parse().ok(), caps["m"].parse::<u32>().ok()?, caps["d"].parse::<u32>().ok()?)
+	} else if let Some(caps) = self.month_day.captures(text) {
+	    (year, caps["m"].parse::<u32>().ok()?, caps["d"].parse::<u32>().ok()?)
+	} else {
+	    return None;
+	};
+	if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+	    return None;
+	}
+	Some(y.map(|y: u32| y * 10000 + m * 100 + d))
+    }
+
+    fn headings(lines: &[&str], format: Format) -> Vec<Heading> {
+	let mut list = Vec::<Heading>::new();
+	let mut in_code = false;
+	for (i, line) in lines.iter().enumerate() {
+	    let (level, text) = match format {
+		Format::Org => match org::heading_text(line) {
+		    Some(text) => (line.len() - line.trim_start_matches('*').len(), text),
+		    None => continue,
+		},
+		_ => {
+		    if line.trim_start().starts_with("```") {
+			in_code = !in_code;
+		    }
+		    let rest = line.trim_start_matches('#');
+		    let level = line.len() - rest.len();
+		    if in_code || level == 0 || level > 6 || !(rest.is_empty() || rest.starts_with(' ')) {
+			continue;
+		    }
+		    (level, rest.trim().trim_end_matches('#').trim_end())
+		},
+	    };
+	    list.push(Heading { line: i, level, text: text.to_string() });
+	}
+	list
+    }
+
+    fn read(&self, text: &str, format: Format) -> Entry {
+	match format {
+	    Format::Org => self.org.parse(text),
+	    _ => markdown::parse(text),
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn org() {
+	let buf = "#+TITLE: 2022年8月\n\
+		   前置き\n\
+		   * 2022-08-05 東京へ\n\
+		   東京駅は混んでいた。\n\
+		   ** 夜\n\
+		   帰った。\n\
+		   * 2022-08-06\n\
+		   雨。\n";
+	let splitter = Splitter::new();
+	let (preamble, sections) = splitter.split(buf, Format::Org, None).unwrap();
+	assert_eq!(preamble.title, "2022年8月");
+	assert_eq!(preamble.body, "前置き");
+	assert_eq!(sections.len(), 2);
+	assert_eq!(sections[0].anchor, "2022-08-05");
+	assert_eq!(sections[0].date, Some(20220805));
+	assert_eq!(sections[0].lines, (3, 6));
+	assert_eq!(sections[0].entry.title, "2022-08-05 東京へ");
+	assert_eq!(sections[0].entry.body, "東京駅は混んでいた。\n夜\n帰った。");
+	assert_eq!(sections[1].anchor, "2022-08-06");
+	assert_eq!(sections[1].lines, (7, 8));
+
+	assert!(splitter.split("* 買い物\nメモ\n", Format::Org, None).is_none());
+    }
+
+    #[test]
+    fn markdown() {
+	let buf = "# 2022年8月\n\
+		   \n\
+		   ## 8月5日\n\
+		   \n\
+		   東京へ。\n\
+		   \n\
+		   ```\n\
+		   ## 8月6日 ではない\n\
+		   ```\n\
+		   ## 8月6日\n\
+		   \n\
+		   雨。\n";
+	let (_, sections) = Splitter::new().split(buf, Format::Markdown, None).unwrap();
+	assert_eq!(sections.len(), 2);
+	assert_eq!(sections[0].date, Some(20220805));
+	assert_eq!(sections[0].entry.title, "8月5日");
+	assert_eq!(sections[0].entry.body, "東京へ。\n## 8月6日 ではない");
+	assert_eq!(sections[1].anchor, "2022-08-06");
+
+	// 見出しの中の数字は年にしない
+	let buf = "# 2022年8月\n\
+		   ## 8月5日 2000円の買い物\n\
+		   本。\n\
+		   ## 8月6日\n\
+		   雨。\n";
+	let (_, sections) = Splitter::new().split(buf, Format::Markdown, Some(20230801)).unwrap();
+	assert_eq!(sections[0].date, Some(20220805));
+	assert_eq!(sections[1].date, Some(20220806));
+	let (_, sections) = Splitter::new().split("## 8月5日 2000円の買い物\n", Format::Markdown, Some(20220801)).unwrap();
+	assert_eq!(sections[0].date, Some(20220805));
+
+	// 年が分からなければ日付は無し
+	let (_, sections) = Splitter::new().split("## 8月5日\n東京へ。\n", Format::Markdown, None).unwrap();
+	assert_eq!(sections[0].date, None);
+	assert_eq!(sections[0].anchor, "p1");
+	let (_, sections) = Splitter::new().split("## 8月5日\n東京へ。\n", Format::Markdown, Some(20220801)).unwrap();
+	assert_eq!(sections[0].date, Some(20220805));
+    }
+}
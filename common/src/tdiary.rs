@@ -0,0 +1,217 @@
+use regex::Regex;
+use crate::entry::Entry;
+use crate::html;
+use crate::markdown;
+use crate::section::Section;
+
+// tDiary の日記データ (.td2)。1ファイルに何日分も入っている。
+//   TDIARY2.01.00
+//   Date: 20220805
+//   Title: 東京へ
+//   Last-Modified: 1659700000
+//   Visible: true
+//   Format: tDiary
+//   (空行)
+//   本文
+//   .
+// 本文中の "." で始まる行は "." を一つ重ねてある。
+// 1日を1文書にして、path#2022-08-05 で索引に登録する。
+
+// ファイル全体を1文書として読む。分割しない時用。
+pub fn parse(buf: &str) -> Entry {
+    let days = split(buf);
+    let mut entry = Entry::default();
+    entry.title = days.first().map(|day| day.entry.title.clone()).unwrap_or_default();
+    entry.body = days.iter()
+	.map(|day| format!("{}\n{}", day.entry.title, day.entry.body))
+	.collect::<Vec<String>>()
+	.join("\n");
+    entry
+}
+
+pub fn split(buf: &str) -> Vec<Section> {
+    let lines: Vec<&str> = buf.lines().collect();
+    let markup = Markup::new();
+    let mut sections = Vec::<Section>::new();
+    let mut i = 0;
+    if lines.first().is_some_and(|line| line.starts_with("TDIARY")) {
+	i = 1;
+    }
+    while i < lines.len() {
+	// 日の間の空行は読み飛ばす
+	if lines[i].trim().is_empty() {
+	    i += 1;
+	    continue;
+	}
+	// ヘッダ
+	let mut headers = Vec::<(String, String)>::new();
+	while i < lines.len() && !lines[i].trim().is_empty() {
+	    if let Some((name, value)) = lines[i].split_once(':') {
+		headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+	    }
+	    i += 1;
+	}
+	let blank = i;
+	i += 1;
+	// 本文
+	let start = i;
+	while i < lines.len() && lines[i] != "." {
+	    i += 1;
+	}
+	let end = i;
+	i += 1;
+
+	let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+	let date = match header("date").and_then(parse_date) {
+	    Some(date) => date,
+	    None => continue,
+	};
+	let style = header("format").unwrap_or("tDiary").to_ascii_lowercase();
+	let mut entry = Entry::default();
+	entry.body = markup.strip(&unescape(&lines[start.min(end)..end]), &style);
+	entry.title = match header("title") {
+	    Some(title) if !title.is_empty() => html::decode_entities(title),
+	    _ => entry.body.lines().next().unwrap_or("").to_string(),
+	};
+	entry.attrs.push((String::from("format"), style));
+	if header("visible") == Some("false") {
+	    entry.attrs.push((String::from("visible"), String::from("false")));
+	}
+
+	let mut anchor = format!("{:04}-{:02}-{:02}", date / 10000, date / 100 % 100, date % 100);
+	if sections.iter().any(|s| s.anchor == anchor) {
+	    anchor = format!("{}-{}", anchor, start + 1);
+	}
+	sections.push(Section {
+	    anchor,
+	    date: Some(date),
+	    // 表示側は1行目を見出しとして読み飛ばすので、ヘッダの後の空行から
+	    lines: (blank + 1, end),
+	    entry,
+	});
+    }
+    sections
+}
+
+// 1日分の本文 (ヘッダの後の空行の次から "." の前まで)。
+// 表示側が path#2022-08-05 の範囲を読む時に使う。style は Format: の値。
+pub fn parse_day(text: &str, style: &str) -> Entry {
+    let lines: Vec<&str> = text.lines().collect();
+    Entry {
+	body: Markup::new().strip(&unescape(&lines), &style.to_ascii_lowercase()),
+	..Default::default()
+    }
+}
+
+fn unescape(lines: &[&str]) -> String {
+    lines.iter()
+	.map(|line| line.strip_prefix('.').unwrap_or(line))
+	.collect::<Vec<&str>>()
+	.join("\n")
+}
+
+fn parse_date(s: &str) -> Option<u32> {
+    if s.len() != 8 {
+	return None;
+    }
+    let date: u32 = s.parse().ok()?;
+    let (m, d) = (date / 100 % 100, date % 100);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+	return None;
+    }
+    Some(date)
+}
+
+struct Markup {
+    plugin: Regex,
+    link: Regex,
+    tag: Regex,
+}
+
+impl Markup {
+    fn new() -> Self {
+	Markup {
+	    plugin: Regex::new(r"\{\{.*?\}\}").unwrap(),
+	    link: Regex::new(r"\[\[(?:([^|\]]*)\|)?([^\]]*)\]\]").unwrap(),
+	    tag: Regex::new(r"<[^>]*>").unwrap(),
+	}
+    }
+
+    // 書式ごとに記号を落とす
+    fn strip(&self, body: &str, style: &str) -> String {
+	if matches!(style, "markdown" | "gfm" | "commonmark") {
+	    return markdown::parse(body).body;
+	}
+	let (plugin, link, tag) = (&self.plugin, &self.link, &self.tag);
+	body.lines()
+	    .map(|line| {
+		let line = match style {
+		    "wiki" => {
+			let line = line.trim_start_matches('!').trim_start_matches(['*', '-']);
+			let line = plugin.replace_all(line, "");
+			link.replace_all(&line, |caps: &regex::Captures| {
+			    caps.get(1).map_or(&caps[2], |text| text.as_str()).to_string()
+			}).into_owned()
+		    },
+		    "rd" => line.trim_start_matches('=').to_string(),
+		    _ => plugin.replace_all(line, "").into_owned(),
+		};
+		html::decode_entities(tag.replace_all(&line, "").trim())
+	    })
+	    .filter(|line| !line.is_empty())
+	    .collect::<Vec<String>>()
+	    .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let buf = "TDIARY2.01.00\n\
+		   Date: 20220805\n\
+		   Title: 東京へ\n\
+		   Last-Modified: 1659700000\n\
+		   Visible: true\n\
+		   Format: tDiary\n\
+		   \n\
+		   [旅行] 東京駅\n\
+		   <p>東京駅は混んでいた。</p>\n\
+		   ..で始まる行\n\
+		   .\n\
+		   \n\
+		   Date: 20220806\n\
+		   Title: \n\
+		   Visible: false\n\
+		   Format: Wiki\n\
+		   \n\
+		   ! 雨\n\
+		   [[天気|https://example.com/]]を見た。{{amazon '4000000000'}}\n\
+		   .\n";
+	let days = split(buf);
+	assert_eq!(days.len(), 2);
+	assert_eq!(days[0].anchor, "2022-08-05");
+	assert_eq!(days[0].date, Some(20220805));
+	assert_eq!(days[0].lines, (7, 10));
+	assert_eq!(days[0].entry.title, "東京へ");
+	assert_eq!(days[0].entry.body, "[旅行] 東京駅\n東京駅は混んでいた。\n.で始まる行");
+	assert_eq!(days[1].anchor, "2022-08-06");
+	assert_eq!(days[1].lines, (17, 19));
+	assert_eq!(days[1].entry.title, "雨");
+	assert_eq!(days[1].entry.body, "雨\n天気を見た。");
+	assert!(days[1].entry.attrs.contains(&(String::from("visible"), String::from("false"))));
+
+	// 表示側は lines の範囲の2行目から読む
+	let lines: Vec<&str> = buf.lines().collect();
+	let (start, end) = days[0].lines;
+	assert_eq!(parse_day(&lines[start..end].join("\n"), "tDiary").body, days[0].entry.body);
+	let (start, end) = days[1].lines;
+	assert_eq!(parse_day(&lines[start..end].join("\n"), "wiki").body, days[1].entry.body);
+
+	let entry = parse(buf);
+	assert_eq!(entry.title, "東京へ");
+	assert!(entry.body.contains("天気を見た。"));
+    }
+}
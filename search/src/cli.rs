@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::prelude::*;
+use sudachi::prelude::MorphemeList;
+use sudachi::analysis::Mode;
+use sudachi::analysis::stateful_tokenizer::StatefulTokenizer;
+use sudachi::dic::dictionary::JapaneseDictionary;
+use crate::hits;
+use crate::hits::SortOrder;
+use crate::config::Config;
+use rsdiary_common::decode::Decoder;
+use rsdiary_common::reader::Formats;
+use crate::index;
+use crate::index::{Index, StaleList};
+use crate::parser;
+use crate::responder::ResultFile;
+use crate::urlmap::UrlMapper;
+
+const USAGE: &str = "usage: rsdiary_search [--format table|paths|json|grep] [--sort newest|oldest|relevance] [--limit N] QUERY...";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Table,
+    Paths,
+    Json,	// 1行に1件の JSON
+    Grep,	// path:line:text。Emacs の M-x grep や Vim の quickfix 用
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Options {
+    pub format: Format,
+    pub sort: SortOrder,
+    pub limit: Option<usize>,
+    pub query: String,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut format = Format::Table;
+    let mut sort = SortOrder::Newest;
+    let mut limit = None;
+    let mut words = Vec::<&str>::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+	match arg.as_str() {
+	    "-f" | "--format" => {
+		format = match iter.next().map(|s| s.as_str()) {
+		    Some("table") => Format::Table,
+		    Some("paths") => Format::Paths,
+		    Some("json") => Format::Json,
+		    Some("grep") => Format::Grep,
+		    Some(s) => return Err(format!("unknown format: {}", s)),
+		    None => return Err(String::from(USAGE)),
+		};
+	    },
+	    "-s" | "--sort" => {
+		sort = match iter.next().map(|s| s.as_str()) {
+		    Some(s @ ("newest" | "oldest" | "relevance")) => SortOrder::from_param(Some(s)),
+		    Some(s) => return Err(format!("unknown sort order: {}", s)),
+		    None => return Err(String::from(USAGE)),
+		};
+	    },
+	    "-n" | "--limit" => {
+		limit = match iter.next().map(|s| s.parse::<usize>()) {
+		    Some(Ok(n)) => Some(n),
+		    Some(Err(e)) => return Err(format!("bad limit: {}", e)),
+		    None => return Err(String::from(USAGE)),
+		};
+	    },
+	    "--" => {
+		words.extend(iter.by_ref().map(|s| s.as_str()));
+	    },
+	    s if s.starts_with('-') && s.len() > 1 => {
+		return Err(format!("unknown option: {}\n{}", s, USAGE));
+	    },
+	    s => words.push(s),
+	}
+    }
+    if words.is_empty() {
+	return Err(String::from(USAGE));
+    }
+
+    Ok(Options {
+	format,
+	sort,
+	limit,
+	query: words.join(" "),
+    })
+}
+
+// 終了コードは grep に倣って、見つかれば 0、見つからなければ 1、検索式がおかしければ 2。
+pub fn run(opts: &Options,
+	   config: &Config,
+	   dict: &JapaneseDictionary,
+	   index: &Index) -> i32 {
+    let mut analyzer = StatefulTokenizer::new(dict, Mode::A);
+    let mut parser = parser::Parser::new(&mut analyzer, &index.words, &index.matrix, &index.docs, &index.postings);
+    let result = match parser.parse(opts.query.clone()) {
+	Ok(result) => result,
+	Err(msg) => {
+	    eprintln!("rsdiary_search: {}", msg);
+	    return 2;
+	},
+    };
+    let scores = parser.scores(&result);
+    let terms = parser.query_terms();
+    let mut hits = hits::make_hits(result, &index.docs, &scores, opts.sort);
+    if hits.is_empty() {
+	return 1;
+    }
+    if let Some(limit) = opts.limit {
+	hits.truncate(limit);
+    }
+
+    let urls = UrlMapper::new(&config.url_template, &config.roots);
+    let decoder = match Decoder::new(&config.encodings) {
+	Ok(decoder) => decoder,
+	Err(msg) => {
+	    eprintln!("rsdiary_search: {}", msg);
+	    return 2;
+	},
+    };
+    let formats = match Formats::new(&config.formats) {
+	Ok(formats) => formats,
+	Err(msg) => {
+	    eprintln!("rsdiary_search: {}", msg);
+	    return 2;
+	},
+    };
+    let stale = if config.prune_stale { Some(StaleList::new(&config.index_dir)) } else { None };
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let terms_set: HashSet<String> = HashSet::from_iter(terms.iter().cloned());
+    for hit in hits {
+	let doc = index.docs.get(&hit.path);
+	let lines = match opts.format {
+	    Format::Paths => vec![hit.path],
+	    Format::Table => {
+		let rf = ResultFile::new(hit, doc, &terms, &urls, &decoder, &formats, stale.as_ref());
+		vec![format!("{:<10}  {:>6.2}  {}  {}",
+			     rf.date.as_deref().unwrap_or("-"), rf.score, rf.path, rf.title)]
+	    },
+	    Format::Json => {
+		let rf = ResultFile::new(hit, doc, &terms, &urls, &decoder, &formats, stale.as_ref());
+		vec![serde_json::to_string(&rf).expect("Failed to serialize.")]
+	    },
+	    // 変換した文書は、行番号が元のファイルと合わないので出さない
+	    Format::Grep if doc.and_then(|doc| doc.attr("filtered")).is_some() => vec![],
+	    Format::Grep => {
+		let (file, _) = index::split_anchor(&hit.path, doc);
+		let range = doc.and_then(|doc| doc.lines());
+		grep_lines(file, range, &terms_set, dict, &decoder).into_iter()
+		    .map(|(lineno, text)| format!("{}:{}:{}", file, lineno, text))
+		    .collect()
+	    },
+	};
+	for line in lines {
+	    if writeln!(out, "{}", line).is_err() {
+		return 0;	// パイプの先が閉じた
+	    }
+	}
+    }
+    0
+}
+
+// ヒットした文書だけを読み直して、検索語を含む行を探す。
+// 行番号は 1 始まり。見出しで分けた文書はその範囲の行だけ。
+fn grep_lines(path: &str, range: Option<(usize, usize)>, terms: &HashSet<String>,
+	      dict: &JapaneseDictionary, decoder: &Decoder) -> Vec<(usize, String)> {
+    let buf = match fs::read(path) {
+	Ok(bytes) => decoder.decode(path, &bytes),
+	Err(why) => {
+	    eprintln!("couldn't read {}: {}", path, why);
+	    return vec![];
+	},
+    };
+
+    // rsdiary_add が索引を作る時と同じ分け方で探す
+    let mut analyzers = [
+	StatefulTokenizer::new(dict, Mode::A),
+	StatefulTokenizer::new(dict, Mode::B),
+	StatefulTokenizer::new(dict, Mode::C),
+    ];
+    let mut list = Vec::<(usize, String)>::new();
+    for (i, line) in buf.lines().enumerate() {
+	if line.trim().is_empty() || range.is_some_and(|(start, end)| i + 1 < start || i + 1 > end) {
+	    continue;
+	}
+	let found = analyzers.iter_mut().any(|ana| {
+	    ana.reset().push_str(line);
+	    ana.do_tokenize().expect("Failed to tokenize.");
+	    let mut morphs = MorphemeList::empty(ana.dict_clone());
+	    morphs.collect_results(ana).expect("Failed to collect results.");
+	    let found = morphs.iter().any(|m| {
+		terms.contains(&m.surface().to_string()) || terms.contains(m.normalized_form())
+	    });
+	    found
+	});
+	if found {
+	    list.push((i + 1, line.to_string()));
+	}
+    }
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn args(list: &[&str]) -> Vec<String> {
+	list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test() {
+	let opts = parse_args(&args(&["東京", "AND", "date:2022"])).unwrap();
+	assert_eq!(opts, Options {
+	    format: Format::Table,
+	    sort: SortOrder::Newest,
+	    limit: None,
+	    query: String::from("東京 AND date:2022"),
+	});
+
+	let opts = parse_args(&args(&["--format", "json", "-s", "relevance", "-n", "5", "--", "-x"])).unwrap();
+	assert_eq!(opts, Options {
+	    format: Format::Json,
+	    sort: SortOrder::Relevance,
+	    limit: Some(5),
+	    query: String::from("-x"),
+	});
+
+	let opts = parse_args(&args(&["-f", "grep", "東京"])).unwrap();
+	assert_eq!(opts.format, Format::Grep);
+
+	assert!(parse_args(&args(&[])).is_err());
+	assert!(parse_args(&args(&["--format", "xml", "東京"])).is_err());
+	assert!(parse_args(&args(&["--sort", "random", "東京"])).is_err());
+	assert!(parse_args(&args(&["--limit"])).is_err());
+	assert!(parse_args(&args(&["--verbose", "東京"])).is_err());
+    }
+
+    #[test]
+    fn grep() {
+	let config = sudachi::config::Config::new(
+	    Some(std::path::PathBuf::from("../t/sudachi.rs/resources/sudachi.json")),
+	    Some(std::path::PathBuf::from("../t/sudachi.rs/resources")),
+	    Some(std::path::PathBuf::from("../t/sudachi.rs/resources/system.dic")),
+	).expect("Failed to load config file");
+	let dict = JapaneseDictionary::from_cfg(&config).expect("Failed to read dict.");
+	let dir = std::env::temp_dir().join(format!("rsdiary-grep-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let file = dir.join("20220805.txt");
+	fs::write(&file, "東京へ\n\n京都は暑い\n東京に戻る\n").unwrap();
+	let path = file.to_str().unwrap();
+	let decoder = Decoder::new(&HashMap::new()).unwrap();
+	let terms = HashSet::from([String::from("東京")]);
+
+	let lines = grep_lines(path, None, &terms, &dict, &decoder);
+	assert_eq!(lines, vec![(1, String::from("東京へ")), (4, String::from("東京に戻る"))]);
+	let lines = grep_lines(path, Some((2, 4)), &terms, &dict, &decoder);
+	assert_eq!(lines, vec![(4, String::from("東京に戻る"))]);
+
+	fs::remove_dir_all(&dir).unwrap();
+    }
+}
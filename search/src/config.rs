@@ -0,0 +1,167 @@
+use std::env;
+use std::collections::HashMap;
+use serde::Deserialize;
+use rsdiary_common::config::{load_config_file, take_options};
+
+// 設定ファイルの値は、環境変数、コマンドラインの順に上書きされる。
+// 項目     設定ファイル   環境変数               オプション
+// 索引     index_dir      INDEX_DIR              --index-dir
+// 辞書     res_dir        RES_DIR                --res-dir
+// 文書     roots          -                      -
+// テンプレ template_dir   RSDIARY_TEMPLATE_DIR   --template-dir   (無ければ組み込みのもの)
+// 静的     static_dir     RSDIARY_STATIC_DIR     --static-dir     (無ければ組み込みのもの)
+// 開発用   dev            RSDIARY_DEV            --dev            (テンプレートを自動で読み直す)
+// 掃除     prune_stale    RSDIARY_PRUNE_STALE    --prune-stale    (無くなったファイルを index.stale.txt に書く)
+// 待受     listen         RSDIARY_LISTEN         --listen
+// URL      url_template   RSDIARY_URL_TEMPLATE   --url-template
+// 文字コード [encodings]  -                      -                (ディレクトリ = "euc-jp" など。無ければ推測)
+// 形式     [formats]      -                      -                (拡張子 = "markdown" など。表示で使う)
+// rsdiary_add と同じファイルを読むので、知らない項目は無視する。
+
+const OPTIONS: [&str; 7] = ["config", "index-dir", "res-dir", "template-dir", "static-dir", "listen", "url-template"];
+const FLAGS: [&str; 2] = ["dev", "prune-stale"];
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ConfigFile {
+    index_dir: Option<String>,
+    res_dir: Option<String>,
+    roots: Vec<String>,
+    template_dir: Option<String>,
+    static_dir: Option<String>,
+    listen: Option<String>,
+    url_template: Option<String>,
+    dev: Option<bool>,
+    prune_stale: Option<bool>,
+    encodings: HashMap<String, String>,
+    formats: HashMap<String, String>,
+}
+
+pub struct Config {
+    pub index_dir: String,
+    pub res_dir: String,
+    pub roots: Vec<String>,
+    pub template_dir: Option<String>,
+    pub static_dir: Option<String>,
+    pub listen: String,
+    pub url_template: String,
+    pub dev: bool,
+    pub prune_stale: bool,
+    pub encodings: HashMap<String, String>,
+    pub formats: HashMap<String, String>,
+}
+
+impl Config {
+    // args から設定用のオプションを取り除き、残りはそのまま返す。
+    pub fn load(args: &mut Vec<String>) -> Result<Config, String> {
+	let opts = take_options(args, &OPTIONS, &FLAGS)?;
+	let file: ConfigFile = load_config_file(&opts)?;
+	Self::resolve(file, &opts, |key| env::var(key).ok())
+    }
+
+    fn resolve<F>(file: ConfigFile, opts: &HashMap<String, String>, getenv: F) -> Result<Config, String>
+    where F: Fn(&str) -> Option<String> {
+	let pick = |opt: &str, var: &str, value: Option<String>| {
+	    opts.get(opt).cloned().or_else(|| getenv(var)).or(value)
+	};
+	let index_dir = pick("index-dir", "INDEX_DIR", file.index_dir)
+	    .ok_or("index_dir is not set (set index_dir in the config file, INDEX_DIR or --index-dir)")?;
+	let res_dir = pick("res-dir", "RES_DIR", file.res_dir)
+	    .ok_or("res_dir is not set (set res_dir in the config file, RES_DIR or --res-dir)")?;
+	let template_dir = pick("template-dir", "RSDIARY_TEMPLATE_DIR", file.template_dir);
+	let static_dir = pick("static-dir", "RSDIARY_STATIC_DIR", file.static_dir);
+	let listen = pick("listen", "RSDIARY_LISTEN", file.listen)
+	    .unwrap_or(String::from("0.0.0.0:9292"));
+	if !listen.contains(':') {
+	    return Err(format!("listen must be HOST:PORT: {}", listen));
+	}
+	let url_template = pick("url-template", "RSDIARY_URL_TEMPLATE", file.url_template)
+	    .unwrap_or(String::from("http://localhost/{stem}"));
+	let flag = |opt: &str, var: &str, value: Option<bool>| {
+	    match pick(opt, var, value.map(|b| b.to_string())) {
+		Some(s) => matches!(s.as_str(), "true" | "1" | "yes"),
+		None => false,
+	    }
+	};
+	let dev = flag("dev", "RSDIARY_DEV", file.dev);
+	let prune_stale = flag("prune-stale", "RSDIARY_PRUNE_STALE", file.prune_stale);
+
+	Ok(Config {
+	    index_dir,
+	    res_dir,
+	    roots: file.roots,
+	    template_dir,
+	    static_dir,
+	    listen,
+	    url_template,
+	    dev,
+	    prune_stale,
+	    encodings: file.encodings,
+	    formats: file.formats,
+	})
+    }
+
+    pub fn listen_host_port(&self) -> (&str, &str) {
+	self.listen.rsplit_once(':').unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+	list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn options() {
+	let mut a = args(&["--listen", "127.0.0.1:8080", "-f", "json", "--dev", "--index-dir=/idx", "東京", "--", "--res-dir"]);
+	let opts = take_options(&mut a, &OPTIONS, &FLAGS).unwrap();
+	assert_eq!(a, args(&["-f", "json", "東京", "--", "--res-dir"]));
+	assert_eq!(opts["listen"], "127.0.0.1:8080");
+	assert_eq!(opts["index-dir"], "/idx");
+	assert_eq!(opts["dev"], "true");
+	assert_eq!(opts.len(), 3);
+
+	let mut a = args(&["--config"]);
+	assert!(take_options(&mut a, &OPTIONS, &FLAGS).is_err());
+    }
+
+    #[test]
+    fn resolve() {
+	let file: ConfigFile = toml::from_str(r#"
+index_dir = "/file/index"
+res_dir = "/file/res"
+roots = ["/diary/split"]
+date_pattern = "ignored by rsdiary_search"
+
+[encodings]
+"/diary/old" = "shift_jis"
+
+[formats]
+txt = "markdown"
+"#).unwrap();
+	let mut opts = HashMap::<String, String>::new();
+	opts.insert(String::from("res-dir"), String::from("/opt/res"));
+	let env = |key: &str| match key {
+	    "INDEX_DIR" => Some(String::from("/env/index")),
+	    "RES_DIR" => Some(String::from("/env/res")),
+	    _ => None,
+	};
+	let config = Config::resolve(file, &opts, env).unwrap();
+	assert_eq!(config.index_dir, "/env/index");
+	assert_eq!(config.res_dir, "/opt/res");
+	assert_eq!(config.roots, vec![String::from("/diary/split")]);
+	assert_eq!(config.template_dir, None);
+	assert_eq!(config.static_dir, None);
+	assert!(!config.dev);
+	assert!(!config.prune_stale);
+	assert_eq!(config.encodings["/diary/old"], "shift_jis");
+	assert_eq!(config.formats["txt"], "markdown");
+	assert_eq!(config.listen_host_port(), ("0.0.0.0", "9292"));
+
+	let err = Config::resolve(ConfigFile::default(), &HashMap::new(), |_| None);
+	assert!(err.is_err());
+    }
+}
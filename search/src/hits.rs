@@ -0,0 +1,233 @@
+use std::collections::{HashSet, HashMap};
+use std::cmp::Ordering;
+use std::ops::Range;
+use crate::index::Doc;
+
+pub struct Hit {
+    pub path: String,
+    pub date: Option<u32>,
+    pub score: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SortOrder {
+    Newest,
+    Oldest,
+    Relevance,
+}
+
+impl SortOrder {
+    // 知らない値や指定なしは新しい順
+    pub fn from_param(s: Option<&str>) -> Self {
+	match s {
+	    Some("oldest") => SortOrder::Oldest,
+	    Some("relevance") => SortOrder::Relevance,
+	    _ => SortOrder::Newest,
+	}
+    }
+
+    pub fn as_str(&self) -> &'static str {
+	match self {
+	    SortOrder::Newest => "newest",
+	    SortOrder::Oldest => "oldest",
+	    SortOrder::Relevance => "relevance",
+	}
+    }
+}
+
+pub fn format_date(date: u32) -> String {
+    format!("{:04}-{:02}-{:02}", date / 10000, date / 100 % 100, date % 100)
+}
+
+// 日付順に並べた時の前後の文書。日付の無い文書には前後が無い。
+pub fn neighbors<'a>(path: &str, docs: &'a HashMap<String, Doc>) -> (Option<&'a String>, Option<&'a String>) {
+    let date = match docs.get(path).and_then(|doc| doc.date) {
+	Some(date) => date,
+	None => return (None, None),
+    };
+    let cur = (date, path);
+    let mut prev: Option<(u32, &String)> = None;
+    let mut next: Option<(u32, &String)> = None;
+    for (p, doc) in docs.iter() {
+	let d = match doc.date {
+	    Some(d) => d,
+	    None => continue,
+	};
+	let key = (d, p.as_str());
+	if key < cur && prev.is_none_or(|(pd, pp)| (pd, pp.as_str()) < key) {
+	    prev = Some((d, p));
+	}
+	if key > cur && next.is_none_or(|(nd, np)| key < (nd, np.as_str())) {
+	    next = Some((d, p));
+	}
+    }
+    (prev.map(|(_, p)| p), next.map(|(_, p)| p))
+}
+
+pub const DEFAULT_PER_PAGE: usize = 20;
+pub const MAX_PER_PAGE: usize = 100;
+const MAX_PAGE: usize = usize::MAX / MAX_PER_PAGE;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Paging {
+    pub page: usize,	// 1 始まり
+    pub per_page: usize,
+}
+
+impl Paging {
+    pub fn from_params(page: Option<&str>, per_page: Option<&str>) -> Self {
+	// 大きすぎるページは範囲外として扱えればよいので、掛け算があふれない所で止める
+	let page = page.and_then(|s| s.parse().ok()).filter(|n| *n >= 1).unwrap_or(1).min(MAX_PAGE);
+	let per_page = per_page.and_then(|s| s.parse().ok()).filter(|n| *n >= 1).unwrap_or(DEFAULT_PER_PAGE);
+	Paging {
+	    page,
+	    per_page: per_page.min(MAX_PER_PAGE),
+	}
+    }
+
+    pub fn pages(&self, total: usize) -> usize {
+	total.div_ceil(self.per_page).max(1)
+    }
+
+    // ページが範囲外なら空になる
+    pub fn range(&self, total: usize) -> Range<usize> {
+	let start = (self.page - 1).saturating_mul(self.per_page).min(total);
+	let end = (start + self.per_page).min(total);
+	start..end
+    }
+}
+
+pub fn make_hits(files: HashSet<String>,
+		 docs: &HashMap<String, Doc>,
+		 scores: &HashMap<String, f64>,
+		 order: SortOrder) -> Vec<Hit> {
+    let mut hits: Vec<Hit> = files.into_iter().map(|path| {
+	let date = docs.get(&path).and_then(|doc| doc.date);
+	let score = scores.get(&path).cloned().unwrap_or(0.0);
+	Hit {
+	    path,
+	    date,
+	    score,
+	}
+    }).collect();
+    sort_hits(&mut hits, order);
+    hits
+}
+
+// 日付の無いものはどちらの順でも最後に回す。同じ値の時はパスで決める。
+pub fn sort_hits(hits: &mut [Hit], order: SortOrder) {
+    hits.sort_by(|a, b| {
+	match order {
+	    SortOrder::Newest => {
+		cmp_date(a.date, b.date, true)
+		    .then_with(|| b.path.cmp(&a.path))
+	    },
+	    SortOrder::Oldest => {
+		cmp_date(a.date, b.date, false)
+		    .then_with(|| a.path.cmp(&b.path))
+	    },
+	    SortOrder::Relevance => {
+		b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal)
+		    .then_with(|| cmp_date(a.date, b.date, true))
+		    .then_with(|| b.path.cmp(&a.path))
+	    },
+	}
+    });
+}
+
+fn cmp_date(a: Option<u32>, b: Option<u32>, newest_first: bool) -> Ordering {
+    match (a, b) {
+	(Some(a), Some(b)) if newest_first => b.cmp(&a),
+	(Some(a), Some(b)) => a.cmp(&b),
+	(Some(_), None) => Ordering::Less,
+	(None, Some(_)) => Ordering::Greater,
+	(None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(path: &str, date: Option<u32>, score: f64) -> Hit {
+	Hit {
+	    path: String::from(path),
+	    date,
+	    score,
+	}
+    }
+
+    fn paths(hits: &[Hit]) -> Vec<&str> {
+	hits.iter().map(|h| h.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test() {
+	let mut hits = vec![
+	    hit("a", Some(20220805), 1.0),
+	    hit("b", None, 3.0),
+	    hit("c", Some(20220803), 2.0),
+	    hit("d", Some(20220805), 2.0),
+	];
+
+	sort_hits(&mut hits, SortOrder::Newest);
+	assert_eq!(paths(&hits), vec!["d", "a", "c", "b"]);
+
+	sort_hits(&mut hits, SortOrder::Oldest);
+	assert_eq!(paths(&hits), vec!["c", "a", "d", "b"]);
+
+	sort_hits(&mut hits, SortOrder::Relevance);
+	assert_eq!(paths(&hits), vec!["b", "d", "c", "a"]);
+
+	assert_eq!(SortOrder::from_param(None), SortOrder::Newest);
+	assert_eq!(SortOrder::from_param(Some("relevance")), SortOrder::Relevance);
+	assert_eq!(SortOrder::from_param(Some("foo")), SortOrder::Newest);
+    }
+
+    #[test]
+    fn paging() {
+	let paging = Paging::from_params(None, None);
+	assert_eq!(paging, Paging { page: 1, per_page: DEFAULT_PER_PAGE });
+	let paging = Paging::from_params(Some("0"), Some("10000"));
+	assert_eq!(paging, Paging { page: 1, per_page: MAX_PER_PAGE });
+	let paging = Paging::from_params(Some("x"), Some("-1"));
+	assert_eq!(paging, Paging { page: 1, per_page: DEFAULT_PER_PAGE });
+
+	let paging = Paging::from_params(Some("3"), Some("10"));
+	assert_eq!(paging.pages(0), 1);
+	assert_eq!(paging.pages(25), 3);
+	assert_eq!(paging.pages(30), 3);
+	assert_eq!(paging.range(25), 20..25);
+	assert_eq!(paging.range(15), 15..15);
+
+	let paging = Paging::from_params(Some("18446744073709551615"), Some("100"));
+	assert_eq!(paging.page, usize::MAX / MAX_PER_PAGE);
+	assert_eq!(paging.range(25), 25..25);
+	let paging = Paging { page: usize::MAX, per_page: 10 };
+	assert_eq!(paging.range(25), 25..25);
+    }
+
+    #[test]
+    fn neighbor() {
+	let mut docs = HashMap::<String, Doc>::new();
+	for (path, date) in [("20220803p01.est", Some(20220803)),
+			     ("20220805p01.est", Some(20220805)),
+			     ("20220805p02.est", Some(20220805)),
+			     ("20220810p01.est", Some(20220810)),
+			     ("nodate.est", None)] {
+	    docs.insert(String::from(path), Doc { date, ..Default::default() });
+	}
+	let name = |p: Option<&String>| p.map(|s| s.to_string());
+
+	let (prev, next) = neighbors("20220805p01.est", &docs);
+	assert_eq!(name(prev), Some(String::from("20220803p01.est")));
+	assert_eq!(name(next), Some(String::from("20220805p02.est")));
+
+	let (prev, next) = neighbors("20220810p01.est", &docs);
+	assert_eq!(name(prev), Some(String::from("20220805p02.est")));
+	assert_eq!(next, None);
+
+	assert_eq!(neighbors("nodate.est", &docs), (None, None));
+	assert_eq!(format_date(20220805), "2022-08-05");
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::{HashSet, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Mutex;
+use serde::Serialize;
+use rsdiary_common::field;
+use crate::hits;
+
+// index.docs.txt の1行。rsdiary_add が古いと date 以外は無い。
+// path \t date \t title \t length \t excerpt \t uri \t name=value ...
+#[derive(Default)]
+pub struct Doc {
+    pub date: Option<u32>,
+    pub title: Option<String>,
+    pub length: Option<usize>,	// 本文の文字数
+    pub excerpt: Option<String>,	// 本文の先頭
+    pub uri: Option<String>,	// 文書自身が持つ URL
+    pub attrs: Vec<(String, String)>,
+}
+
+impl Doc {
+    // 見出しで分けた文書なら、元のファイルでの行の範囲 (1 始まり、両端を含む)
+    pub fn lines(&self) -> Option<(usize, usize)> {
+	let (start, end) = self.attr("lines")?.split_once('-')?;
+	Some((start.parse().ok()?, end.parse().ok()?))
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+	self.attrs.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+// 見出しで分けた文書は path#anchor で登録されている。
+// ファイル名にも # は使えるので、lines のある文書だけ最後の # で分ける。
+pub fn split_anchor<'a>(path: &'a str, doc: Option<&Doc>) -> (&'a str, Option<&'a str>) {
+    match path.rsplit_once('#') {
+	Some((file, anchor)) if doc.is_some_and(|doc| doc.lines().is_some()) => (file, Some(anchor)),
+	_ => (path, None),
+    }
+}
+
+fn index_file_path(dir: &str, typ: &str, suffix: &str) -> String {
+    let mut path = dir.to_string();
+    path.push('/');
+    path.push_str("index.");
+    path.push_str(typ);
+    path.push_str(".txt");
+    path.push_str(suffix);
+    path
+}
+
+pub fn read_index_words(dir: &str) -> HashMap<String, u32> {
+    let path = index_file_path(dir, "words", "");
+    let path = Path::new(&path);
+    let file = match File::open(path) {
+	Err(why) => panic!("couldn't open {}: {}", path.display(), why),
+	Ok(file) => file,
+    };
+    let file = BufReader::new(file);
+
+    let mut map = HashMap::<String, u32>::new();
+
+    for (word_id, line) in (0..).zip(file.lines()) {
+	let line = line.unwrap();
+	map.insert(line, word_id);
+    }
+
+    map
+}
+
+pub fn read_index_matrix(dir: &str) -> HashMap<String, HashSet<u32>> {
+    let path = index_file_path(dir, "matrix", "");
+    let path = Path::new(&path);
+    let file = File::open(path).expect("Failed to open index.matrix.txt.");
+    let file = BufReader::new(file);
+
+    let mut mat = HashMap::<String, HashSet<u32>>::new();
+
+    for line in file.lines() {
+	let line = line.unwrap();
+	let mut iter = line.split_ascii_whitespace();
+	let path = iter.next().unwrap();
+	let mut word_ids = HashSet::<u32>::new();
+	for s in iter {
+	    let id: u32 = s.parse().unwrap();
+	    word_ids.insert(id);
+	}
+	mat.insert(path.to_string(), word_ids);
+    }
+
+    mat
+}
+
+// rsdiary_add が古いと index.docs.txt が無いので、その場合は空とする。
+pub fn read_index_docs(dir: &str) -> HashMap<String, Doc> {
+    let path = index_file_path(dir, "docs", "");
+    let path = Path::new(&path);
+    let mut docs = HashMap::<String, Doc>::new();
+    let file = match File::open(path) {
+	Err(why) => {
+	    eprintln!("couldn't open {}: {}", path.display(), why);
+	    return docs;
+	},
+	Ok(file) => file,
+    };
+    let file = BufReader::new(file);
+
+    for line in file.lines() {
+	let line = line.unwrap();
+	let mut iter = line.split('\t');
+	let path = iter.next().unwrap();
+	let date = iter.next().and_then(|s| s.parse().ok());
+	let title = iter.next().map(field::unescape);
+	let length = iter.next().and_then(|s| s.parse().ok());
+	let excerpt = iter.next().map(field::unescape);
+	let uri = iter.next().filter(|s| !s.is_empty()).map(field::unescape);
+	let attrs = iter.filter_map(|s| {
+	    let (name, value) = s.split_once('=')?;
+	    Some((field::unescape(name), field::unescape(value)))
+	}).collect();
+	docs.insert(path.to_string(), Doc { date, title, length, excerpt, uri, attrs });
+    }
+
+    docs
+}
+
+// 単語から文書への転置索引と、前方一致用に整列した単語表。
+// 検索のたびに作ると全単語を舐めることになるので、索引を読んだ時に一度だけ作る。
+pub struct Postings {
+    pub docs: HashMap<u32, HashSet<String>>,
+    pub terms: Vec<(String, u32)>,
+}
+
+impl Postings {
+    pub fn new(words: &HashMap<String, u32>, matrix: &HashMap<String, HashSet<u32>>) -> Self {
+	let mut docs = HashMap::<u32, HashSet<String>>::new();
+	for (fname, word_ids) in matrix {
+	    for word_id in word_ids {
+		docs.entry(*word_id).or_default().insert(fname.clone());
+	    }
+	}
+	let mut terms: Vec<(String, u32)> = words.iter().map(|(w, id)| (w.clone(), *id)).collect();
+	terms.sort();
+	Postings { docs, terms }
+    }
+}
+
+// 起動時に読んだ索引
+pub struct Index {
+    pub words: HashMap<String, u32>,
+    pub matrix: HashMap<String, HashSet<u32>>,
+    pub docs: HashMap<String, Doc>,
+    pub postings: Postings,
+}
+
+impl Index {
+    pub fn read(dir: &str) -> Self {
+	let words = read_index_words(dir);
+	let matrix = read_index_matrix(dir);
+	let docs = read_index_docs(dir);
+	let postings = Postings::new(&words, &matrix);
+	Index { words, matrix, docs, postings }
+    }
+}
+
+// 検索中に見つかった、索引にあるのに無くなっているファイル。
+// index.stale.txt に追記しておくと、次の rsdiary_add が索引から消す。
+pub struct StaleList {
+    path: String,
+    seen: Mutex<HashSet<String>>,
+}
+
+impl StaleList {
+    pub fn new(dir: &str) -> Self {
+	StaleList {
+	    path: index_file_path(dir, "stale", ""),
+	    seen: Mutex::new(HashSet::new()),
+	}
+    }
+    pub fn add(&self, path: &str) {
+	let mut seen = self.seen.lock().unwrap();
+	if !seen.insert(path.to_string()) {
+	    return;
+	}
+	let file = OpenOptions::new().create(true).append(true).open(&self.path);
+	let result = file.and_then(|mut file| writeln!(file, "{}", path));
+	match result {
+	    Ok(()) => eprintln!("{} is missing; scheduled for pruning", path),
+	    Err(why) => eprintln!("couldn't write {}: {}", self.path, why),
+	}
+    }
+}
+
+// トップページに出す索引の概要
+#[derive(Serialize)]
+pub struct Stats {
+    pub docs: usize,
+    pub words: usize,
+    pub first_date: Option<String>,
+    pub last_date: Option<String>,
+}
+
+impl Stats {
+    pub fn new(words: &HashMap<String, u32>, matrix: &HashMap<String, HashSet<u32>>,
+	       docs: &HashMap<String, Doc>) -> Self {
+	let dates = matrix.keys().filter_map(|path| docs.get(path)?.date);
+	let (first, last) = dates.fold((None, None), |(first, last): (Option<u32>, Option<u32>), date| {
+	    (Some(first.map_or(date, |d| d.min(date))), Some(last.map_or(date, |d| d.max(date))))
+	});
+	Stats {
+	    docs: matrix.len(),
+	    words: words.len(),
+	    first_date: first.map(hits::format_date),
+	    last_date: last.map(hits::format_date),
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+	let words = HashMap::from([("今日".to_string(), 0), ("天気".to_string(), 1)]);
+	let matrix = HashMap::from([
+	    ("a".to_string(), HashSet::from([0])),
+	    ("b".to_string(), HashSet::from([0, 1])),
+	    ("c".to_string(), HashSet::from([1])),
+	]);
+	let docs = HashMap::from([
+	    ("a".to_string(), Doc { date: Some(20220805), ..Default::default() }),
+	    ("b".to_string(), Doc { date: Some(20210101), ..Default::default() }),
+	    ("c".to_string(), Doc { date: None, ..Default::default() }),
+	    ("gone".to_string(), Doc { date: Some(19990101), ..Default::default() }),
+	]);
+	let stats = Stats::new(&words, &matrix, &docs);
+	assert_eq!(stats.docs, 3);
+	assert_eq!(stats.words, 2);
+	assert_eq!(stats.first_date.as_deref(), Some("2021-01-01"));
+	assert_eq!(stats.last_date.as_deref(), Some("2022-08-05"));
+
+	let postings = Postings::new(&words, &matrix);
+	assert_eq!(postings.docs[&1], HashSet::from(["b".to_string(), "c".to_string()]));
+	assert_eq!(postings.terms, vec![("今日".to_string(), 0), ("天気".to_string(), 1)]);
+    }
+
+    #[test]
+    fn docs() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-docs-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("index.docs.txt"),
+		       "/a.est\t20220805\t東京へ\t14\t駅は\\t混んで\\nいた。\thttp://example.com/a\tmdate=2022-08-06\n\
+			/b.est\t\n").unwrap();
+	let docs = read_index_docs(dir.to_str().unwrap());
+	let a = &docs["/a.est"];
+	assert_eq!(a.date, Some(20220805));
+	assert_eq!(a.title.as_deref(), Some("東京へ"));
+	assert_eq!(a.length, Some(14));
+	assert_eq!(a.excerpt.as_deref(), Some("駅は\t混んで\nいた。"));
+	assert_eq!(a.uri.as_deref(), Some("http://example.com/a"));
+	assert_eq!(a.attrs, vec![(String::from("mdate"), String::from("2022-08-06"))]);
+	let b = &docs["/b.est"];
+	assert_eq!(b.date, None);
+	assert_eq!(b.title, None);
+	assert_eq!(b.uri, None);
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn section() {
+	let doc = Doc { attrs: vec![(String::from("lines"), String::from("3-6"))], ..Default::default() };
+	assert_eq!(split_anchor("/d/2022-08.org#2022-08-05", Some(&doc)), ("/d/2022-08.org", Some("2022-08-05")));
+	assert_eq!(split_anchor("/d/C#メモ.org#2022-08-05", Some(&doc)), ("/d/C#メモ.org", Some("2022-08-05")));
+	assert_eq!(split_anchor("/d/20220805p01.est", Some(&doc)), ("/d/20220805p01.est", None));
+	assert_eq!(split_anchor("/d/C#メモ.txt", Some(&Doc::default())), ("/d/C#メモ.txt", None));
+	assert_eq!(split_anchor("/d/C#メモ.txt", None), ("/d/C#メモ.txt", None));
+	assert_eq!(doc.lines(), Some((3, 6)));
+	assert_eq!(Doc::default().lines(), None);
+    }
+
+    #[test]
+    fn stale() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-stale-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let stale = StaleList::new(dir.to_str().unwrap());
+	stale.add("/a/b.est");
+	stale.add("/a/c.est");
+	stale.add("/a/b.est");
+	let buf = std::fs::read_to_string(dir.join("index.stale.txt")).unwrap();
+	assert_eq!(buf, "/a/b.est\n/a/c.est\n");
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,15 @@
+mod cli;
+mod config;
+mod hits;
+mod index;
 mod parser;
 mod responder;
+mod router;
+mod snippet;
+mod urlmap;
+
+use rsdiary_common::decode;
+use rsdiary_common::reader;
 
 extern crate simple_server;
 use simple_server::Server;
@@ -7,19 +17,10 @@ use simple_server::Request;
 use simple_server::Response;
 use simple_server::ResponseBuilder;
 
-use std::time::Duration;
-
-use url::Url;
-
-
 use std::env;
+use std::process;
 use std::path::PathBuf;
-use std::collections::{HashSet, HashMap};
-use std::fs::File;
-use std::io::prelude::*;   // write_all
-use std::io::BufReader;
-use std::path::Path;
-use sudachi::prelude::MorphemeList;
+use std::collections::HashMap;
 use sudachi::config::Config;
 use sudachi::analysis::Mode;
 use sudachi::analysis::stateful_tokenizer::StatefulTokenizer;
@@ -36,111 +37,209 @@ fn get_dict() -> JapaneseDictionary {
 }
 */
 
-fn get_q(request: Request<Vec<u8>>) -> Option<String> {
-    let uri = request.uri();
-    let query_str = match uri.query() {
-	Some(query) => query,
-	None => return None,
-    };
-    let mut q = "".to_string();
-    for (k, v) in url::form_urlencoded::parse(query_str.as_bytes()) {
-	if k == "q" {
-	    return Some(v.to_string());
+fn get_params(request: &Request<Vec<u8>>) -> HashMap<String, String> {
+    let mut params = HashMap::<String, String>::new();
+    if let Some(query_str) = request.uri().query() {
+	for (k, v) in url::form_urlencoded::parse(query_str.as_bytes()) {
+	    params.insert(k.to_string(), v.to_string());
 	}
     }
-    None
+    params
 }
 
 fn serve(request: Request<Vec<u8>>,
 	 mut response: ResponseBuilder,
+	 responder: &responder::Responder,
 	 dict: &JapaneseDictionary,
-	 index_words: &HashMap<String, u32>,
-	 index_matrix: &HashMap<String, HashSet<u32>>) -> Response<Vec<u8>> {
-    let q = match get_q(request) {
-	Some(q) => q,
-	None => return response.status(404).body("err".as_bytes().to_vec()).unwrap(),
+	 index: &index::Index) -> Response<Vec<u8>> {
+    let params = get_params(&request);
+    let api = match router::route(request.method().as_str(), request.uri().path()) {
+	router::Route::Home => false,
+	router::Route::Api => true,
+	router::Route::Entry(id) => {
+	    return serve_entry(id, &params, response, responder, dict, index);
+	},
+	router::Route::Static(name) => {
+	    return match responder.static_file(name) {
+		Some(buf) => response.status(200)
+		    .header("Content-Type", router::content_type(name))
+		    .body(buf).unwrap(),
+		None => serve_error(404, "Not Found", response, responder),
+	    };
+	},
+	router::Route::NotFound => return serve_error(404, "Not Found", response, responder),
+	router::Route::MethodNotAllowed => {
+	    response.header("Allow", "GET, HEAD");
+	    return serve_error(405, "Method Not Allowed", response, responder);
+	},
+    };
+    let q = match params.get("q") {
+	Some(q) if !q.trim().is_empty() => q.clone(),
+	_ if api => {
+	    let json = serde_json::json!({ "error": "missing parameter: q" }).to_string();
+	    return response.status(400)
+		.header("Content-Type", "application/json; charset=utf-8")
+		.body(json.into_bytes()).unwrap();
+	},
+	_ => {
+	    // q が無ければ検索フォームを出す
+	    let stats = index::Stats::new(&index.words, &index.matrix, &index.docs);
+	    let html = responder.make_home_html(&stats);
+	    return response.status(200)
+		.header("Content-Type", "text/html; charset=utf-8")
+		.body(html.into_bytes()).unwrap();
+	},
     };
+    let sort = hits::SortOrder::from_param(params.get("sort").map(|s| s.as_str()));
+    let paging = hits::Paging::from_params(params.get("page").map(|s| s.as_str()),
+					   params.get("per_page").map(|s| s.as_str()));
 
     let mut analyzer = StatefulTokenizer::new(dict, Mode::A);
-    let mut parser = parser::Parser::new(&mut analyzer, index_words, index_matrix);
-    let result = parser.parse(q.clone());
-
-    let responder = responder::Responder::new();
-    let html = responder.make_html(q, 1, result);
+    let mut parser = parser::Parser::new(&mut analyzer, &index.words, &index.matrix, &index.docs, &index.postings);
+    let result = match parser.parse(q.clone()) {
+	Ok(result) => result,
+	Err(msg) if api => {
+	    let json = serde_json::json!({ "error": msg }).to_string();
+	    return response.status(400)
+		.header("Content-Type", "application/json; charset=utf-8")
+		.body(json.into_bytes()).unwrap();
+	},
+	Err(msg) => return serve_error(400, &msg, response, responder),
+    };
+    let scores = parser.scores(&result);
+    let terms = parser.query_terms();
+    let hits = hits::make_hits(result, &index.docs, &scores, sort);
+
+    if api {
+	let json = responder.make_json(q, &terms, sort, paging, hits, &index.docs);
+	return response.status(200)
+	    .header("Content-Type", "application/json; charset=utf-8")
+	    .body(json.into_bytes()).unwrap();
+    }
+    let html = responder.make_html(q, &terms, sort, paging, hits, &index.docs);
 
-    response.status(200).body(html.as_bytes().to_vec()).unwrap()
+    response.status(200)
+	.header("Content-Type", "text/html; charset=utf-8")
+	.body(html.as_bytes().to_vec()).unwrap()
 }
 
-fn index_file_path(typ: &str, suffix: &str) -> String {
-    let mut path = env::var("INDEX_DIR").expect("Couldn't get INDEX_DIR");
-    path.push_str("/");
-    path.push_str("index.");
-    path.push_str(typ);
-    path.push_str(".txt");
-    path.push_str(suffix);
-    path
+fn serve_error(status: u16,
+	       message: &str,
+	       mut response: ResponseBuilder,
+	       responder: &responder::Responder) -> Response<Vec<u8>> {
+    let html = responder.make_error_html(status, message);
+    response.status(status)
+	.header("Content-Type", "text/html; charset=utf-8")
+	.body(html.into_bytes()).unwrap()
 }
 
-fn read_index_words() -> HashMap<String, u32> {
-    let path = index_file_path("words", "");
-    let path = Path::new(&path);
-    let file = match File::open(&path) {
-	Err(why) => panic!("couldn't open {}: {}", path.display(), why),
-	Ok(file) => file,
+fn serve_entry(id: &str,
+	       params: &HashMap<String, String>,
+	       mut response: ResponseBuilder,
+	       responder: &responder::Responder,
+	       dict: &JapaneseDictionary,
+	       index: &index::Index) -> Response<Vec<u8>> {
+    let path = match responder.urls().find_entry(id, index.matrix.keys()) {
+	Some(path) => path,
+	None => return serve_error(404, "Not Found", response, responder),
     };
-    let file = BufReader::new(file);
 
-    let mut map = HashMap::<String, u32>::new();
-    let mut word_id: u32 = 0;
+    // 検索結果から来た時は検索語をハイライトする
+    let q = params.get("q");
+    let terms = match q {
+	Some(q) => {
+	    let mut analyzer = StatefulTokenizer::new(dict, Mode::A);
+	    let mut parser = parser::Parser::new(&mut analyzer, &index.words, &index.matrix, &index.docs, &index.postings);
+	    // 構文がおかしければハイライトしないだけ
+	    match parser.parse(q.clone()) {
+		Ok(_) => parser.query_terms(),
+		Err(_) => vec![],
+	    }
+	},
+	None => vec![],
+    };
 
-    for line in file.lines() {
-	let line = line.unwrap();
-	map.insert(line, word_id);
-	word_id += 1;
+    match responder.make_entry_html(path, q, &terms, &index.docs) {
+	Some(html) => response.status(200)
+	    .header("Content-Type", "text/html; charset=utf-8")
+	    .body(html.as_bytes().to_vec()).unwrap(),
+	None => serve_error(404, "Not Found", response, responder),
     }
-
-    map
 }
 
-fn read_index_matrix() -> HashMap<String, HashSet<u32>> {
-    let path = index_file_path("matrix", "");
-    let path = Path::new(&path);
-    let file = File::open(&path).expect("Failed to open index.matrix.txt.");
-    let file = BufReader::new(file);
-
-    let mut mat = HashMap::<String, HashSet<u32>>::new();
-
-    for line in file.lines() {
-	let line = line.unwrap();
-	let mut iter = line.split_ascii_whitespace();
-	let path = iter.next().unwrap();
-	let mut word_ids = HashSet::<u32>::new();
-	for s in iter {
-	    let id: u32 = s.parse().unwrap();
-	    word_ids.insert(id);
-	}
-	mat.insert(path.to_string(), word_ids);
-    }
-
-    mat
-}
-
-fn get_dict() -> JapaneseDictionary {
+fn get_dict(res_dir: &str) -> JapaneseDictionary {
+    let mut json = PathBuf::from(res_dir);
+    json.push("sudachi.json");
+    let mut sys_dic = PathBuf::from(res_dir);
+    sys_dic.push("system.dic");
     let config = Config::new(
-	Some(PathBuf::from("../t/sudachi.rs/resources/sudachi.json")),
-	Some(PathBuf::from("../t/sudachi.rs/resources")),
-	Some(PathBuf::from("../t/sudachi.rs/resources/system.dic")),
+	Some(json),
+	Some(PathBuf::from(res_dir)),
+	Some(sys_dic),
     ).expect("Failed to load config file");
     JapaneseDictionary::from_cfg(&config).expect("Failed to read dict.")
 }
 
 fn main() {
-    let dict = get_dict();
-    let index_words = read_index_words();
-    let index_matrix = read_index_matrix();
-    let mut server = Server::new(move |request, mut response| {
-	Ok(serve(request, response, &dict, &index_words, &index_matrix))
+    let mut args: Vec<String> = env::args().collect();
+    let config = match config::Config::load(&mut args) {
+	Ok(config) => config,
+	Err(msg) => {
+	    eprintln!("rsdiary_search: {}", msg);
+	    process::exit(1);
+	},
+    };
+
+    // 設定以外の引数があればコマンドラインで検索する
+    let opts = if args.len() > 1 {
+	match cli::parse_args(&args[1..]) {
+	    Ok(opts) => Some(opts),
+	    Err(msg) => {
+		eprintln!("{}", msg);
+		process::exit(2);
+	    },
+	}
+    } else {
+	None
+    };
+
+    let dict = get_dict(&config.res_dir);
+    let index = index::Index::read(&config.index_dir);
+    if let Some(opts) = opts {
+	let code = cli::run(&opts, &config, &dict, &index);
+	process::exit(code);
+    }
+    let urls = urlmap::UrlMapper::new(&config.url_template, &config.roots);
+    let mut responder = match responder::Responder::new(config.template_dir.as_ref(), config.static_dir.as_ref(),
+						 config.dev, urls) {
+	Ok(responder) => responder,
+	Err(msg) => {
+	    eprintln!("rsdiary_search: {}", msg);
+	    process::exit(1);
+	},
+    };
+    if config.prune_stale {
+	responder.schedule_pruning(index::StaleList::new(&config.index_dir));
+    }
+    match decode::Decoder::new(&config.encodings) {
+	Ok(decoder) => responder.set_decoder(decoder),
+	Err(msg) => {
+	    eprintln!("rsdiary_search: {}", msg);
+	    process::exit(1);
+	},
+    }
+    match reader::Formats::new(&config.formats) {
+	Ok(formats) => responder.set_formats(formats),
+	Err(msg) => {
+	    eprintln!("rsdiary_search: {}", msg);
+	    process::exit(1);
+	},
+    }
+    let (host, port) = config.listen_host_port();
+    let (host, port) = (host.to_string(), port.to_string());
+    let mut server = Server::new(move |request, response| {
+	Ok(serve(request, response, &responder, &dict, &index))
     });
     server.dont_serve_static_files();
-    server.listen("0.0.0.0", "9292");
+    server.listen(&host, &port);
 }
@@ -1,11 +1,9 @@
-use std::path::PathBuf;
 use std::collections::HashSet;
 use std::collections::HashMap;
 use sudachi::prelude::MorphemeList;
-use sudachi::config::Config;
-use sudachi::analysis::Mode;
 use sudachi::analysis::stateful_tokenizer::StatefulTokenizer;
 use sudachi::dic::dictionary::JapaneseDictionary;
+use crate::index::{Doc, Postings};
 
 /*
 ors    = ands ( `OR` ands )*
@@ -16,6 +14,8 @@ nots   = `NOT` nots
 parens = `(` ors `)`
        | word
 word   = WORD
+       | PATTERN                  `*` (任意の文字列) と `?` (任意の1文字) を含む語
+       | `date:` DATES            date:2022, date:2022-08, date:2022-08-01..2022-09-15, date:..2022-08 など
 
 ( あいう AND たちつ ) ( かきく OR さしす )
 NOT ( あいう AND たちつ ) ( かきく OR さしす )
@@ -42,39 +42,32 @@ pub struct Parser<'a, 'b> {
     analyzer: &'a mut StatefulTokenizer<&'b JapaneseDictionary>,
     words: &'a HashMap<String, u32>,
     matrix: &'a HashMap<String, HashSet<u32>>,
-    imat: HashMap<u32, HashSet<String>>,
+    docs: &'a HashMap<String, Doc>,
+    postings: &'a Postings,
+    negated: bool,
+    query_words: HashSet<u32>,	// NOT の付いていない検索語。関連度の計算に使う
+    error: Option<String>,	// date: の書式など、構文以外の誤り
 }
 
 impl<'a, 'b> Parser<'a, 'b> {
     pub fn new(analyzer: &'a mut StatefulTokenizer<&'b JapaneseDictionary>,
 	       words: &'a HashMap<String, u32>,
-	       matrix: &'a HashMap<String, HashSet<u32>>) -> Parser<'a, 'b> {
-	let mut imat = HashMap::<u32, HashSet<String>>::new();
-	for (fname, word_ids) in matrix {
-	    for word_id in word_ids {
-		let set = match imat.get_mut(word_id) {
-		    Some(set) => set,
-		    None => {
-			imat.insert(*word_id, HashSet::<String>::new());
-			match imat.get_mut(word_id) {
-			    Some(set) => set,
-			    None => panic!("why?"),
-			}
-		    }
-		};
-		set.insert(fname.clone());
-	    }
-	}
-
+	       matrix: &'a HashMap<String, HashSet<u32>>,
+	       docs: &'a HashMap<String, Doc>,
+	       postings: &'a Postings) -> Parser<'a, 'b> {
 	Parser {
 	    analyzer,
 	    words,
 	    matrix,
-	    imat,
+	    docs,
+	    postings,
+	    negated: false,
+	    query_words: HashSet::<u32>::new(),
+	    error: None,
 	}
     }
 
-    fn get_token<'z>(&self, tokens: &Vec<&'z str>, pos: usize) -> TokenType<'z> {
+    fn get_token<'z>(&self, tokens: &[&'z str], pos: usize) -> TokenType<'z> {
 	if pos >= tokens.len() {
 	    return TokenType::None;
 	}
@@ -94,23 +87,30 @@ impl<'a, 'b> Parser<'a, 'b> {
 	if s == ")" {
 	    return TokenType::Rpar;
 	}
-	return TokenType::Other(s);
+	TokenType::Other(s)
     }
     
-    pub fn parse(&mut self, string: String) -> HashSet<String> {
+    // 構文が正しくなければ、どこがおかしいかを返す
+    pub fn parse(&mut self, string: String) -> Result<HashSet<String>, String> {
 	let tokens: Vec<&str> = string.split_ascii_whitespace().collect();
-	match self.ors(&tokens, 0) {
-	    RetVal::Tree(r, rpos) => {
-		if rpos != tokens.len() {
-		    panic!("syntax error! (length not match, {}, {})", rpos, tokens.len());
-		}
-		r
+	self.negated = false;
+	self.query_words.clear();
+	self.error = None;
+	let retval = self.ors(&tokens, 0);
+	if let Some(msg) = self.error.take() {
+	    return Err(msg);
+	}
+	match retval {
+	    RetVal::Tree(r, rpos) if rpos == tokens.len() => Ok(r),
+	    RetVal::Tree(_, rpos) => Err(format!("syntax error near '{}'", tokens[rpos])),
+	    RetVal::None => match tokens.first() {
+		Some(token) => Err(format!("syntax error near '{}'", token)),
+		None => Err(String::from("empty query")),
 	    },
-	    RetVal::None => panic!("syntax error! (parse error)"),
 	}
     }
     
-    fn ors(&mut self, tokens: &Vec<&str>, pos: usize) -> RetVal {
+    fn ors(&mut self, tokens: &[&str], pos: usize) -> RetVal {
 	let ands = self.ands(tokens, pos);
 	let (mut ands, mut pos) = match ands {
 	    RetVal::Tree(ands, pos) => (ands, pos),
@@ -138,7 +138,7 @@ impl<'a, 'b> Parser<'a, 'b> {
 	}
     }
     
-    fn ands(&mut self, tokens: &Vec<&str>, pos: usize) -> RetVal {
+    fn ands(&mut self, tokens: &[&str], pos: usize) -> RetVal {
 	let nots = self.nots(tokens, pos);
 	let (mut nots, mut pos) = match nots {
 	    RetVal::Tree(nots, pos) => (nots, pos),
@@ -183,34 +183,36 @@ impl<'a, 'b> Parser<'a, 'b> {
 	}
     }
     
-    fn nots(&mut self, tokens: &Vec<&str>, mut pos: usize) -> RetVal {
+    fn nots(&mut self, tokens: &[&str], mut pos: usize) -> RetVal {
 	match self.get_token(tokens, pos) {
 	    TokenType::Not => {
 		pos += 1;
+		self.negated = !self.negated;
 		let nots = self.nots(tokens, pos);
+		self.negated = !self.negated;
 		match nots {
 		    RetVal::Tree(some_nots, rpos) => {
 			let all = self.all();
 			let res = HashSet::from_iter(all.difference(&some_nots).cloned());
-			return RetVal::Tree(res, rpos);
+			RetVal::Tree(res, rpos)
 		    },
-		    RetVal::None => return RetVal::None,
+		    RetVal::None => RetVal::None,
 		}
 	    },
 	    _ => {
 		match self.parens(tokens, pos) {
 		    RetVal::Tree(parens, pos) => {
-			return RetVal::Tree(parens, pos);
+			RetVal::Tree(parens, pos)
 		    },
 		    _ => {
-			return RetVal::None;
+			RetVal::None
 		    }
 		}
 	    },
 	}
     }
 
-    fn parens(&mut self, tokens: &Vec<&str>, mut pos: usize) -> RetVal {
+    fn parens(&mut self, tokens: &[&str], mut pos: usize) -> RetVal {
 	match self.get_token(tokens, pos) {
 	    TokenType::Lpar => {
 		pos += 1;
@@ -220,15 +222,15 @@ impl<'a, 'b> Parser<'a, 'b> {
 			match self.get_token(tokens, pos) {
 			    TokenType::Rpar => {
 				pos += 1;
-				return RetVal::Tree(ors, pos);
+				RetVal::Tree(ors, pos)
 			    },
 			    _ => {
-				return RetVal::None;
+				RetVal::None
 			    },
 			}
 		    },
 		    RetVal::None => {
-			return RetVal::None;
+			RetVal::None
 		    },
 		}
 	    },
@@ -236,18 +238,31 @@ impl<'a, 'b> Parser<'a, 'b> {
 		let word = self.word(tokens, pos);
 		match word {
 		    RetVal::Tree(_, _) => {
-			return word;
+			word
 		    },
 		    _ => {
-			return RetVal::None;
+			RetVal::None
 		    }
 		}
 	    },
 	}
     }
 
-    fn word(&mut self, tokens: &Vec<&str>, mut pos: usize) -> RetVal {
+    fn word(&mut self, tokens: &[&str], mut pos: usize) -> RetVal {
 	match self.get_token(tokens, pos) {
+	    TokenType::Other(tkn) if tkn.starts_with("date:") => {
+		let retval = self.dates(&tkn["date:".len()..]).unwrap_or_else(|msg| {
+		    self.error.get_or_insert(msg);
+		    HashSet::new()
+		});
+		pos += 1;
+		RetVal::Tree(retval, pos)
+	    },
+	    TokenType::Other(tkn) if Self::is_pattern(tkn) => {
+		let retval = self.expand(tkn);
+		pos += 1;
+		RetVal::Tree(retval, pos)
+	    },
 	    TokenType::Other(tkn) => {
 		self.analyzer
 		    .reset()
@@ -264,7 +279,10 @@ impl<'a, 'b> Parser<'a, 'b> {
 		    let empty = HashSet::<String>::new();
 		    let fns = match self.words.get(&s) {
 			Some(word_id) => {
-			    match self.imat.get(word_id) {
+			    if !self.negated {
+				self.query_words.insert(*word_id);
+			    }
+			    match self.postings.docs.get(word_id) {
 				Some(fns) => fns,
 				None => &empty,	// 単語は知ってるけど、該当文書がない
 			    }
@@ -274,12 +292,156 @@ impl<'a, 'b> Parser<'a, 'b> {
 		    retval = HashSet::from_iter(retval.intersection(fns).cloned());
 		}
 		pos += 1;
-		return RetVal::Tree(retval, pos);
+		RetVal::Tree(retval, pos)
 	    },
 	    _ => {
-		return RetVal::None;
+		RetVal::None
+	    },
+	}
+    }
+
+    // 日付の分からない文書は、どの範囲にも含めない。
+    fn dates(&self, spec: &str) -> Result<HashSet<String>, String> {
+	let (lo, hi) = Self::parse_date_range(spec).ok_or(format!("invalid date: {}", spec))?;
+	let iter = self.docs.iter()
+	    .filter(|(_, doc)| doc.date.is_some_and(|d| lo <= d && d <= hi))
+	    .map(|(fname, _)| fname.clone());
+	Ok(HashSet::from_iter(iter))
+    }
+
+    // "2022-08" のような単独の指定はその期間全体、"A..B" は A の始まりから B の終わりまで。
+    // 範囲は YYYYMMDD 形式の整数の組で返す。
+    fn parse_date_range(spec: &str) -> Option<(u32, u32)> {
+	match spec.split_once("..") {
+	    Some((from, to)) => {
+		let lo = if from.is_empty() { 0 } else { Self::parse_date(from)?.0 };
+		let hi = if to.is_empty() { 99999999 } else { Self::parse_date(to)?.1 };
+		Some((lo, hi))
 	    },
+	    None => Self::parse_date(spec),
+	}
+    }
+
+    // 2022, 2022-08, 2022-08-05, 2022/8/5, 202208, 20220805 を受け付ける。
+    fn parse_date(s: &str) -> Option<(u32, u32)> {
+	let parts: Vec<&str> = if s.contains(['-', '/']) {
+	    s.split(['-', '/']).collect()
+	} else {
+	    // 数字以外があると文字の途中で切ることになるので、get で取る
+	    match s.len() {
+		4 => vec![s.get(0..4)?],
+		6 => vec![s.get(0..4)?, s.get(4..6)?],
+		8 => vec![s.get(0..4)?, s.get(4..6)?, s.get(6..8)?],
+		_ => return None,
+	    }
+	};
+	let mut nums = Vec::<u32>::new();
+	for p in parts.iter() {
+	    if p.is_empty() || !p.chars().all(|c| c.is_ascii_digit()) {
+		return None;
+	    }
+	    nums.push(p.parse().ok()?);
+	}
+	// YYYYMMDD に収まらない年は受け付けない
+	if !(1..=9999).contains(&nums[0]) {
+	    return None;
+	}
+	match nums[..] {
+	    [y] => Some((y * 10000 + 101, y * 10000 + 1231)),
+	    [y, m] if (1..=12).contains(&m) => Some((y * 10000 + m * 100 + 1, y * 10000 + m * 100 + 31)),
+	    [y, m, d] if (1..=12).contains(&m) && (1..=31).contains(&d) => {
+		let date = y * 10000 + m * 100 + d;
+		Some((date, date))
+	    },
+	    _ => None,
+	}
+    }
+
+    fn is_pattern(tkn: &str) -> bool {
+	tkn.contains('*') || tkn.contains('?')
+    }
+
+    // ワイルドカードより前の部分で整列済みの単語表を二分探索し、
+    // その範囲の単語だけをパターンと照合する。
+    fn expand(&mut self, pattern: &str) -> HashSet<String> {
+	let prefix_len = pattern.find(['*', '?']).unwrap_or(pattern.len());
+	let prefix = &pattern[..prefix_len];
+	let terms = &self.postings.terms;
+	let start = terms.partition_point(|(w, _)| w.as_str() < prefix);
+
+	let mut retval = HashSet::<String>::new();
+	for (w, word_id) in terms[start..].iter() {
+	    if !w.starts_with(prefix) {
+		break;
+	    }
+	    if !Self::glob_match(pattern, w) {
+		continue;
+	    }
+	    if !self.negated {
+		self.query_words.insert(*word_id);
+	    }
+	    if let Some(fns) = self.postings.docs.get(word_id) {
+		retval.extend(fns.iter().cloned());
+	    }
+	}
+	retval
+    }
+
+    fn glob_match(pattern: &str, s: &str) -> bool {
+	let p: Vec<char> = pattern.chars().collect();
+	let s: Vec<char> = s.chars().collect();
+	let mut pi = 0;
+	let mut si = 0;
+	let mut star: Option<(usize, usize)> = None;	// 直前の `*` の位置と、そこから照合し直す位置
+	while si < s.len() {
+	    if pi < p.len() && (p[pi] == '?' || p[pi] == s[si]) {
+		pi += 1;
+		si += 1;
+	    } else if pi < p.len() && p[pi] == '*' {
+		star = Some((pi, si));
+		pi += 1;
+	    } else if let Some((spi, ssi)) = star {
+		pi = spi + 1;
+		si = ssi + 1;
+		star = Some((spi, ssi + 1));
+	    } else {
+		return false;
+	    }
+	}
+	while pi < p.len() && p[pi] == '*' {
+	    pi += 1;
 	}
+	pi == p.len()
+    }
+
+    // 直前の parse() の検索語のうち、文書に含まれるものの idf の和を関連度とする。
+    pub fn scores(&self, files: &HashSet<String>) -> HashMap<String, f64> {
+	let n = self.matrix.len() as f64;
+	let mut scores = HashMap::<String, f64>::new();
+	for fname in files {
+	    let word_ids = match self.matrix.get(fname) {
+		Some(word_ids) => word_ids,
+		None => continue,
+	    };
+	    let mut score = 0.0;
+	    for word_id in self.query_words.iter() {
+		if !word_ids.contains(word_id) {
+		    continue;
+		}
+		let df = self.postings.docs.get(word_id).map_or(0, |fns| fns.len()) as f64;
+		score += (n / df).ln() + 1.0;
+	    }
+	    scores.insert(fname.clone(), score);
+	}
+	scores
+    }
+
+    // 直前の parse() の検索語 (NOT の付いたものを除く)
+    pub fn query_terms(&self) -> Vec<String> {
+	self.postings.terms.iter()
+	    .filter(|(_, word_id)| self.query_words.contains(word_id))
+	    .map(|(w, _)| w.to_string())
+	    .collect()
     }
 
     fn all(&self) -> HashSet<String> {
@@ -290,6 +452,9 @@ impl<'a, 'b> Parser<'a, 'b> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::PathBuf;
+    use sudachi::config::Config;
+    use sudachi::analysis::Mode;
 
     macro_rules! set {
 	($( $x: expr ), *) => {{
@@ -309,7 +474,7 @@ mod tests {
     }
 
     #[test]
-    fn test<'b>() {
+    fn test() {
 	let dict = get_dict();
 	let mut analyzer = StatefulTokenizer::new(&dict, Mode::C);
 	simple(&mut analyzer);
@@ -319,22 +484,29 @@ mod tests {
 	complex2(&mut analyzer);
 	complex3(&mut analyzer);
 	complex4(&mut analyzer);
+	prefix(&mut analyzer);
+	wildcard(&mut analyzer);
+	date(&mut analyzer);
+	score(&mut analyzer);
+	syntax_error(&mut analyzer);
     }
     
-    fn simple<'a, 'b>(analyzer: &'a mut StatefulTokenizer<&'b JapaneseDictionary>) {
+    fn simple(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
 	let mut words = HashMap::<String, u32>::new();
 	words.insert(String::from("今日"), 1);
 	words.insert(String::from("は"), 2);
 	let mut mat = HashMap::<String, HashSet<u32>>::new();
 	mat.insert(String::from("kyoha.txt"), set!{1, 2});
 	mat.insert(String::from("ha.txt"), set!{1});
-	let mut parser = Parser::new(analyzer, &words, &mat);
-	let result = parser.parse(String::from("今日は"));
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("今日は")).unwrap();
 	
 	assert_eq!(result, set!{String::from("kyoha.txt")});
     }
 
-    fn and<'a, 'b>(analyzer: &'a mut StatefulTokenizer<&'b JapaneseDictionary>) {
+    fn and(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
 	let mut words = HashMap::<String, u32>::new();
 	words.insert(String::from("今日"), 1);
 	words.insert(String::from("は"), 2);
@@ -347,13 +519,15 @@ mod tests {
 	let mut mat = HashMap::<String, HashSet<u32>>::new();
 	mat.insert(String::from("bad.txt"), set!{1, 2, 8, 4, 5, 6, 7});
 	mat.insert(String::from("good.txt"), set!{1, 2, 3, 4, 5, 6, 7});
-	let mut parser = Parser::new(analyzer, &words, &mat);
-	let result = parser.parse(String::from("今日 AND 良い AND 天気"));
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("今日 AND 良い AND 天気")).unwrap();
 
 	assert_eq!(result, set!{String::from("good.txt")});
     }
 
-    fn or<'a, 'b>(analyzer: &'a mut StatefulTokenizer<&'b JapaneseDictionary>) {
+    fn or(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
 	let mut words = HashMap::<String, u32>::new();
 	words.insert(String::from("今日"), 1);
 	words.insert(String::from("は"), 2);
@@ -366,8 +540,10 @@ mod tests {
 	let mut mat = HashMap::<String, HashSet<u32>>::new();
 	mat.insert(String::from("bad.txt"), set!{1, 2, 8, 4, 5, 6, 7});
 	mat.insert(String::from("good.txt"), set!{1, 2, 3, 4, 5, 6, 7});
-	let mut parser = Parser::new(analyzer, &words, &mat);
-	let result = parser.parse(String::from("今日 AND ( 良い OR 悪い ) AND 天気"));
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("今日 AND ( 良い OR 悪い ) AND 天気")).unwrap();
 
 	assert_eq!(result, set!{String::from("good.txt"), String::from("bad.txt")});
     }
@@ -393,7 +569,7 @@ mod tests {
 				if i4 == 1 { set.insert(4); }	// 恵子 (8)
 				if i5 == 1 { set.insert(5); }	// 真知子 (16)
 				if i6 == 1 { set.insert(6); }	// 和美 (32)
-				let no = 32 * i6 + 16 * i5 + 8 * i4 + 4 * i3 + 2 * i2 + 1 * i1;
+				let no = 32 * i6 + 16 * i5 + 8 * i4 + 4 * i3 + 2 * i2 + i1;
 				let fname = format!("file{}.txt", no);
 				mat.insert(fname, set);
 			    }
@@ -404,12 +580,14 @@ mod tests {
 	}
     }
 
-    fn complex1<'a, 'b>(analyzer: &'a mut StatefulTokenizer<&'b JapaneseDictionary>) {
+    fn complex1(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
 	let mut words = HashMap::<String, u32>::new();
 	let mut mat = HashMap::<String, HashSet<u32>>::new();
 	get_complex_index(&mut words, &mut mat);
-	let mut parser = Parser::new(analyzer, &words, &mat);
-	let result = parser.parse(String::from("( 優子 AND 恵子 ) ( 愛子 OR 涼子 )"));
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("( 優子 AND 恵子 ) ( 愛子 OR 涼子 )")).unwrap();
 
 	let fids_vec = vec![11, 13, 15, 27, 29, 31, 43, 45, 47, 59, 61, 63];
 	let fnames_iter = fids_vec.iter().map(|id| format!("file{}.txt", id));
@@ -418,12 +596,14 @@ mod tests {
 	assert_eq!(result, fnames);
     }
 
-    fn complex2<'a, 'b>(analyzer: &'a mut StatefulTokenizer<&'b JapaneseDictionary>) {
+    fn complex2(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
 	let mut words = HashMap::<String, u32>::new();
 	let mut mat = HashMap::<String, HashSet<u32>>::new();
 	get_complex_index(&mut words, &mut mat);
-	let mut parser = Parser::new(analyzer, &words, &mat);
-	let result = parser.parse(String::from("NOT ( 優子 AND 恵子 ) ( 愛子 OR 涼子 )"));
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("NOT ( 優子 AND 恵子 ) ( 愛子 OR 涼子 )")).unwrap();
 
 	let fids_vec = vec![2, 3, 4, 5, 6, 7, 10, 12, 14, 18, 19, 20, 21, 22, 23, 26, 28, 30, 34, 35, 36, 37, 38, 39, 42, 44, 46, 50, 51, 52, 53, 54, 55, 58, 60, 62];
 
@@ -433,12 +613,14 @@ mod tests {
 	assert_eq!(result, fnames);
     }
 
-    fn complex3<'a, 'b>(analyzer: &'a mut StatefulTokenizer<&'b JapaneseDictionary>) {
+    fn complex3(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
 	let mut words = HashMap::<String, u32>::new();
 	let mut mat = HashMap::<String, HashSet<u32>>::new();
 	get_complex_index(&mut words, &mut mat);
-	let mut parser = Parser::new(analyzer, &words, &mat);
-	let result = parser.parse(String::from("NOT ( 優子 AND 恵子 ) AND ( 愛子 OR 涼子 )"));
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("NOT ( 優子 AND 恵子 ) AND ( 愛子 OR 涼子 )")).unwrap();
 
 	let fids_vec = vec![2, 3, 4, 5, 6, 7, 10, 12, 14, 18, 19, 20, 21, 22, 23, 26, 28, 30, 34, 35, 36, 37, 38, 39, 42, 44, 46, 50, 51, 52, 53, 54, 55, 58, 60, 62];
 
@@ -448,12 +630,14 @@ mod tests {
 	assert_eq!(result, fnames);
     }
 
-    fn complex4<'a, 'b>(analyzer: &'a mut StatefulTokenizer<&'b JapaneseDictionary>) {
+    fn complex4(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
 	let mut words = HashMap::<String, u32>::new();
 	let mut mat = HashMap::<String, HashSet<u32>>::new();
 	get_complex_index(&mut words, &mut mat);
-	let mut parser = Parser::new(analyzer, &words, &mat);
-	let result = parser.parse(String::from("( ( 優子 OR 恵子 ) ( 愛子 OR 涼子 ) ) ( 真知子 AND 和美 )"));
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("( ( 優子 OR 恵子 ) ( 愛子 OR 涼子 ) ) ( 真知子 AND 和美 )")).unwrap();
 
 	let fids_vec = vec![51, 53, 55, 58, 59, 60, 61, 62, 63];
 
@@ -462,4 +646,108 @@ mod tests {
 
 	assert_eq!(result, fnames);
     }
+
+    fn get_place_index(words: &mut HashMap<String, u32>, mat: &mut HashMap<String, HashSet<u32>>) {
+	words.insert(String::from("東京"), 1);
+	words.insert(String::from("東京駅"), 2);
+	words.insert(String::from("東京タワー"), 3);
+	words.insert(String::from("京都"), 4);
+	words.insert(String::from("京都駅"), 5);
+	words.insert(String::from("東駅"), 6);
+	mat.insert(String::from("tokyo.txt"), set!{1});
+	mat.insert(String::from("tokyo-st.txt"), set!{2});
+	mat.insert(String::from("tower.txt"), set!{3});
+	mat.insert(String::from("kyoto.txt"), set!{4, 5});
+	mat.insert(String::from("higashi.txt"), set!{6});
+    }
+
+    fn prefix(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
+	let mut words = HashMap::<String, u32>::new();
+	let mut mat = HashMap::<String, HashSet<u32>>::new();
+	get_place_index(&mut words, &mut mat);
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("東京*")).unwrap();
+
+	assert_eq!(result, set!{String::from("tokyo.txt"), String::from("tokyo-st.txt"), String::from("tower.txt")});
+    }
+
+    fn wildcard(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
+	let mut words = HashMap::<String, u32>::new();
+	let mut mat = HashMap::<String, HashSet<u32>>::new();
+	get_place_index(&mut words, &mut mat);
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("*駅 AND NOT 東?駅")).unwrap();
+
+	assert_eq!(result, set!{String::from("kyoto.txt"), String::from("higashi.txt")});
+    }
+
+    fn date(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
+	let mut words = HashMap::<String, u32>::new();
+	words.insert(String::from("天気"), 1);
+	let mut mat = HashMap::<String, HashSet<u32>>::new();
+	let mut docs = HashMap::<String, Doc>::new();
+	for (fname, date) in [("20220731p01.est", Some(20220731)),
+			      ("20220805p01.est", Some(20220805)),
+			      ("20220915p01.est", Some(20220915)),
+			      ("20220916p01.est", Some(20220916)),
+			      ("nodate.est", None)] {
+	    mat.insert(String::from(fname), set!{1});
+	    docs.insert(String::from(fname), Doc { date, ..Default::default() });
+	}
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+
+	let result = parser.parse(String::from("天気 date:2022-08")).unwrap();
+	assert_eq!(result, set!{String::from("20220805p01.est")});
+
+	let result = parser.parse(String::from("date:2022-08-01..2022-09-15")).unwrap();
+	assert_eq!(result, set!{String::from("20220805p01.est"), String::from("20220915p01.est")});
+
+	let result = parser.parse(String::from("date:..2022-08")).unwrap();
+	assert_eq!(result, set!{String::from("20220731p01.est"), String::from("20220805p01.est")});
+
+	let result = parser.parse(String::from("date:20220916..")).unwrap();
+	assert_eq!(result, set!{String::from("20220916p01.est")});
+
+	let result = parser.parse(String::from("NOT date:2022")).unwrap();
+	assert_eq!(result, set!{String::from("nodate.est")});
+
+	assert_eq!(parser.parse(String::from("date:2022-13")), Err(String::from("invalid date: 2022-13")));
+	assert_eq!(parser.parse(String::from("date:日日")), Err(String::from("invalid date: 日日")));
+	assert_eq!(parser.parse(String::from("天気 OR date:999999-01")), Err(String::from("invalid date: 999999-01")));
+    }
+
+    fn score(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
+	let mut words = HashMap::<String, u32>::new();
+	let mut mat = HashMap::<String, HashSet<u32>>::new();
+	get_complex_index(&mut words, &mut mat);
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	let result = parser.parse(String::from("優子 OR 愛子 NOT 涼子")).unwrap();
+	let scores = parser.scores(&result);
+
+	assert_eq!(scores.len(), result.len());
+	assert!(scores["file3.txt"] > scores["file1.txt"]);
+	assert_eq!(scores["file1.txt"], scores["file2.txt"]);
+	assert_eq!(scores["file1.txt"], scores["file33.txt"]);
+	assert_eq!(parser.query_terms(), vec![String::from("優子"), String::from("愛子")]);
+    }
+
+    fn syntax_error(analyzer: &mut StatefulTokenizer<&JapaneseDictionary>) {
+	let mut words = HashMap::<String, u32>::new();
+	let mut mat = HashMap::<String, HashSet<u32>>::new();
+	get_complex_index(&mut words, &mut mat);
+	let docs = HashMap::<String, Doc>::new();
+	let postings = Postings::new(&words, &mat);
+	let mut parser = Parser::new(analyzer, &words, &mat, &docs, &postings);
+	assert_eq!(parser.parse(String::from(")")), Err(String::from("syntax error near ')'")));
+	assert_eq!(parser.parse(String::from("優子 AND")), Err(String::from("syntax error near 'AND'")));
+	assert_eq!(parser.parse(String::from("( 優子 OR 愛子")), Err(String::from("syntax error near '('")));
+	assert!(parser.parse(String::from("優子 AND 愛子")).is_ok());
+    }
 }
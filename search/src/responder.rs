@@ -1,88 +1,472 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::io::BufRead;
-use std::collections::HashSet;
-use std::path::Path;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use rsdiary_common::decode::Decoder;
+use rsdiary_common::reader::{Format, Formats};
+use rsdiary_common::html;
+use rsdiary_common::mime::{self, Message};
 use serde::Serialize;
 use tera::{Context, Tera};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+use crate::hits;
+use crate::hits::{Hit, SortOrder, Paging};
+use crate::index;
+use crate::index::{Doc, Stats, StaleList};
+use crate::snippet;
+use crate::urlmap::UrlMapper;
 
 #[derive(Serialize)]
-struct ResultFile {
-    path: String,
+pub struct ResultFile {
+    pub path: String,
+    pub url: String,
+    pub entry_url: String,
+    pub title: String,
+    pub summary: String,	// ハイライト済みの HTML
+    pub snippet: String,
+    pub date: Option<String>,
+    pub score: f64,
+    pub length: Option<usize>,
+    pub attrs: BTreeMap<String, String>,
+    pub missing: bool,	// 索引にはあるがファイルが読めない
+}
+
+#[derive(Serialize)]
+struct SearchResult<'a> {
+    q: &'a str,
+    sort: &'static str,
+    total: usize,
+    page: usize,
+    per_page: usize,
+    pages: usize,
+    hits: Vec<ResultFile>,
+}
+
+#[derive(Serialize)]
+struct EntryLink {
     url: String,
     title: String,
-    summary: String,
+    date: String,
+}
+
+#[derive(Serialize)]
+struct SortLink {
+    label: &'static str,
+    url: String,
+    current: bool,
 }
 
 impl ResultFile {
-    fn new(path: String) -> Self {
-	let url = Self::make_url(&path);
-	let title = Self::make_title(&path);
-	let summary = Self::make_summary(&path);
+    // 索引に題名と抜粋があればファイルは読まず、消えていないかだけ確かめる。
+    // 古い索引でファイルが読めなければ、その結果だけ missing にして続ける。
+    pub fn new(hit: Hit, doc: Option<&Doc>, terms: &[String], urls: &UrlMapper,
+	       decoder: &Decoder, formats: &Formats, stale: Option<&StaleList>) -> Self {
+	let path = hit.path;
+	let url = match doc.and_then(|doc| doc.uri.clone()) {
+	    Some(uri) => uri,
+	    None => urls.make_url(&path, doc),
+	};
+	let entry_url = urls.entry_url(&path);
+	let length = doc.and_then(|doc| doc.length);
+	let attrs = doc.map(|doc| doc.attrs.iter().cloned().collect()).unwrap_or_default();
+	let stored = doc.and_then(|doc| Some((doc.title.as_ref()?, doc.excerpt.as_ref()?)));
+	let gone = |why: &io::Error| {
+	    if why.kind() == io::ErrorKind::NotFound {
+		if let Some(stale) = stale {
+		    stale.add(&path);
+		}
+	    }
+	};
+	let read = || match read_entry(&path, doc, decoder, formats) {
+	    Ok((title, body)) => {
+		let body = body.lines().map(|line| line.trim()).collect::<Vec<&str>>().join("\n");
+		Some((title, body))
+	    },
+	    Err(why) => {
+		gone(&why);
+		None
+	    },
+	};
+	let removed = stored.is_some() && match fs::metadata(index::split_anchor(&path, doc).0) {
+	    Ok(_) => false,
+	    Err(why) => {
+		gone(&why);
+		why.kind() == io::ErrorKind::NotFound
+	    },
+	};
+	let (title, fragments, missing) = match stored {
+	    Some((title, excerpt)) if removed => (title.clone(), snippet::fragments(excerpt, terms), true),
+	    Some((title, excerpt)) => {
+		// 抜粋より後ろにしか検索語が無ければ、ファイルを読んで前後を出す
+		let truncated = doc.and_then(|doc| doc.length).is_some_and(|len| len > excerpt.chars().count());
+		let body = if truncated && !terms.is_empty() && !snippet::matches(excerpt, terms) {
+		    read().map(|(_, body)| body)
+		} else {
+		    None
+		};
+		(title.clone(), snippet::fragments(body.as_deref().unwrap_or(excerpt), terms), false)
+	    },
+	    None => match read() {
+		Some((title, body)) => (title, snippet::fragments(&body, terms), false),
+		None => (Self::fallback_title(&path), vec![], true),
+	    },
+	};
+	let summary = fragments.iter()
+	    .map(|f| snippet::highlight(f, terms))
+	    .collect::<Vec<String>>()
+	    .join(snippet::SEPARATOR);
+	let snippet = fragments.join(snippet::SEPARATOR);
+	let date = hit.date.map(hits::format_date);
 	Self {
 	    path,
 	    url,
+	    entry_url,
 	    title,
 	    summary,
+	    snippet,
+	    date,
+	    score: hit.score,
+	    length,
+	    attrs,
+	    missing,
 	}
     }
-    fn make_url(path: &String) -> String {
-	let p = Path::new(path);
-	let name = p.file_stem().unwrap();
-	let name = name.to_str().unwrap();
-	format!("http://localhost/{}", name)
-    }
-    fn make_title(path: &String) -> String {
-	let path = Path::new(path);
-	let file = File::open(&path).expect("Failed to open split file.");
-	let mut reader = BufReader::new(file);
-	let mut line = String::new();
-	reader.read_line(&mut line);
-	line.trim().to_string()
-    }
-    fn make_summary(path: &String) -> String {
-	let path = Path::new(path);
-	let file = File::open(&path).expect("Failed to open split file.");
-	let mut reader = BufReader::new(file);
-	let mut buf = String::new();
-	reader.read_line(&mut buf);
-	buf.clear();
-	for line in reader.lines() {
-	    let line = line.unwrap();
-	    let l = line.trim();
-	    buf.push_str(l);
-	    buf.push_str(" ");
+    fn fallback_title(path: &str) -> String {
+	Path::new(path).file_name()
+	    .map(|name| name.to_string_lossy().to_string())
+	    .unwrap_or_else(|| path.to_string())
+    }
+}
+
+// rsdiary_add と同じ読み方で題名と本文にする。UTF-8 でなければ rsdiary_add と同じように文字コードを推測する。
+// 見出しで分けた文書はその範囲だけ読む。
+fn read_entry(path: &str, doc: Option<&Doc>, decoder: &Decoder, formats: &Formats) -> io::Result<(String, String)> {
+    let (path, anchor) = index::split_anchor(path, doc);
+    let buf = match fs::read(path) {
+	Ok(buf) => buf,
+	Err(why) => {
+	    eprintln!("couldn't read {}: {}", path, why);
+	    return Err(why);
+	},
+    };
+    if doc.and_then(|doc| doc.attr("format")) == Some("mail") {
+	return Ok(read_mail(&buf, anchor.and(doc.and_then(|doc| doc.lines()))));
+    }
+    // PDF などは rsdiary_add が取っておいた変換結果を plain として表示する
+    if let Some(cache) = doc.and_then(|doc| doc.attr("filtered")) {
+	let buf = match fs::read(cache) {
+	    Ok(buf) => decoder.decode_detected(path, &buf),
+	    Err(why) => {
+		eprintln!("couldn't read {}: {}", cache, why);
+		return Err(why);
+	    },
+	};
+	let (title, body) = buf.split_once('\n').unwrap_or((&buf, ""));
+	return Ok((title.trim().to_string(), body.trim_end().to_string()));
+    }
+    let buf = decoder.decode(path, &buf);
+    let entry = match (anchor, doc.and_then(|doc| doc.lines())) {
+	// 1行目は見出し。tDiary は format に日ごとの書式が入っている
+	(Some(_), Some((start, end))) => {
+	    let lines: Vec<&str> = buf.lines().skip(start.saturating_sub(1)).take((end + 1).saturating_sub(start.max(1))).collect();
+	    let heading = lines.first().map_or("", |line| line.trim_start_matches(['*', '#']).trim());
+	    let rest = lines.get(1..).unwrap_or_default().join("\n");
+	    let format = formats.get(path);
+	    let style = doc.and_then(|doc| doc.attr("format")).filter(|_| format == Format::Tdiary);
+	    let mut entry = formats.read_part(format, &rest, style);
+	    entry.title = heading.to_string();
+	    entry
+	},
+	_ => formats.read(path, &buf),
+    };
+    Ok((entry.title, entry.body.trim_end().to_string()))
+}
+
+// メールは rsdiary_add と同じように MIME を戻して、件名と本文にする。
+// mbox の1通なら lines の範囲で、1行目は "From " 行。
+fn read_mail(bytes: &[u8], lines: Option<(usize, usize)>) -> (String, String) {
+    let raw = match lines {
+	Some((start, end)) => {
+	    let lines: Vec<&[u8]> = bytes.split(|b| *b == b'\n').collect();
+	    let end = end.min(lines.len());
+	    mime::unescape_mbox(&lines[start.min(end)..end])
+	},
+	None => bytes.to_vec(),
+    };
+    let message = Message::parse(&raw);
+    let body = match message.part("text/plain") {
+	Some(text) => text.to_string(),
+	None => message.part("text/html").map(|text| html::parse(text).body).unwrap_or_default(),
+    };
+    (message.decoded_header("subject").unwrap_or_default(), body.trim_end().to_string())
+}
+
+// 組み込みのテンプレート。template_dir に同じ名前のファイルがあればそちらを使う。
+const TEMPLATES: [(&str, &str); 4] = [
+    ("home.html", include_str!("../templates/home.html")),
+    ("index.html", include_str!("../templates/index.html")),
+    ("entry.html", include_str!("../templates/entry.html")),
+    ("error.html", include_str!("../templates/error.html")),
+];
+
+// 組み込みの静的ファイル。static_dir にあればそちらを使う。
+const STATIC_FILES: [(&str, &[u8]); 1] = [
+    ("style.css", include_bytes!("../static/style.css")),
+];
+
+fn load_templates(template_dir: Option<&String>) -> Result<Tera, String> {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(TEMPLATES.to_vec())
+	.map_err(|e| format!("couldn't compile built-in templates: {:?}", e))?;
+    if let Some(dir) = template_dir {
+	let entries = fs::read_dir(dir)
+	    .map_err(|why| format!("couldn't read {}: {}", dir, why))?;
+	let mut files = Vec::<(PathBuf, Option<String>)>::new();
+	for entry in entries {
+	    let path = entry.map_err(|why| format!("couldn't read {}: {}", dir, why))?.path();
+	    if path.extension().is_some_and(|ext| ext == "html") {
+		let name = path.file_name().and_then(|s| s.to_str()).map(|s| s.to_string());
+		files.push((path, name));
+	    }
 	}
-	buf
+	tera.add_template_files(files)
+	    .map_err(|e| format!("couldn't compile templates in {}: {:?}", dir, e))?;
     }
+    Ok(tera)
+}
+
+// template_dir 内のテンプレートの一番新しい更新時刻
+fn templates_mtime(template_dir: &String) -> Option<SystemTime> {
+    fs::read_dir(template_dir).ok()?
+	.filter_map(|entry| entry.ok())
+	.filter(|entry| entry.path().extension().is_some_and(|ext| ext == "html"))
+	.filter_map(|entry| entry.metadata().ok()?.modified().ok())
+	.max()
 }
 
 pub struct Responder {
+    tera: RwLock<Tera>,
+    template_dir: Option<String>,
+    static_dir: Option<String>,
+    dev: bool,
+    loaded: Mutex<Option<SystemTime>>,
+    urls: UrlMapper,
+    stale: Option<StaleList>,
+    decoder: Decoder,
+    formats: Formats,
 }
 
 impl Responder {
-    pub fn new() -> Self {
-	Responder {}
+    // テンプレートは起動時に一度だけコンパイルする。dev なら変更を見て読み直す。
+    pub fn new(template_dir: Option<&String>, static_dir: Option<&String>, dev: bool,
+	       urls: UrlMapper) -> Result<Self, String> {
+	let tera = load_templates(template_dir)?;
+	let loaded = template_dir.and_then(templates_mtime);
+	Ok(Responder {
+	    tera: RwLock::new(tera),
+	    template_dir: template_dir.cloned(),
+	    static_dir: static_dir.cloned(),
+	    dev,
+	    loaded: Mutex::new(loaded),
+	    urls,
+	    stale: None,
+	    decoder: Decoder::new(&HashMap::new())?,
+	    formats: Formats::new(&HashMap::new())?,
+	})
+    }
+    // 無くなったファイルを書き出し、次の rsdiary_add で索引から消してもらう
+    pub fn schedule_pruning(&mut self, stale: StaleList) {
+	self.stale = Some(stale);
+    }
+    // [encodings] の設定
+    pub fn set_decoder(&mut self, decoder: Decoder) {
+	self.decoder = decoder;
     }
-    pub fn make_html(&self, q: String, page_no: i32, files: HashSet<String>) -> String {
-	let mut tera = match Tera::new("templates/*.html") {
-	    Ok(t) => t,
-	    Err(e) => return format!("{:?}", e)
+    // [formats] の設定
+    pub fn set_formats(&mut self, formats: Formats) {
+	self.formats = formats;
+    }
+    pub fn urls(&self) -> &UrlMapper {
+	&self.urls
+    }
+    fn reload_if_changed(&self) {
+	let dir = match &self.template_dir {
+	    Some(dir) if self.dev => dir,
+	    _ => return,
 	};
-
+	let mut loaded = self.loaded.lock().unwrap();
+	let mtime = templates_mtime(dir);
+	if mtime <= *loaded {
+	    return;
+	}
+	*loaded = mtime;
+	match load_templates(Some(dir)) {
+	    Ok(tera) => {
+		*self.tera.write().unwrap() = tera;
+		eprintln!("reloaded templates in {}", dir);
+	    },
+	    Err(msg) => eprintln!("{}", msg),	// 直るまでは前のものを使う
+	}
+    }
+    fn render(&self, name: &str, ctxt: &Context) -> String {
+	self.reload_if_changed();
+	match self.tera.read().unwrap().render(name, ctxt) {
+	    Ok(html) => html,
+	    Err(e) => {
+		eprintln!("couldn't render {}: {:?}", name, e);
+		self.make_internal_error()
+	    },
+	}
+    }
+    // name は router で確認済みなので、ディレクトリの外は指さない
+    pub fn static_file(&self, name: &str) -> Option<Vec<u8>> {
+	if let Some(dir) = &self.static_dir {
+	    let path = Path::new(dir).join(name);
+	    match fs::read(&path) {
+		Ok(buf) => return Some(buf),
+		Err(why) if why.kind() != std::io::ErrorKind::NotFound => {
+		    eprintln!("couldn't read {}: {}", path.display(), why);
+		},
+		Err(_) => {},
+	    }
+	}
+	STATIC_FILES.iter().find(|(n, _)| *n == name).map(|(_, buf)| buf.to_vec())
+    }
+    pub fn make_home_html(&self, stats: &Stats) -> String {
+	let mut ctxt = Context::new();
+	ctxt.insert("stats", stats);
+	self.render("home.html", &ctxt)
+    }
+    pub fn make_error_html(&self, status: u16, message: &str) -> String {
+	let mut ctxt = Context::new();
+	ctxt.insert("status", &status);
+	ctxt.insert("message", message);
+	self.render("error.html", &ctxt)
+    }
+    pub fn make_json(&self, q: String, terms: &[String], sort: SortOrder, paging: Paging, mut hits: Vec<Hit>,
+		     docs: &HashMap<String, Doc>) -> String {
+	let total = hits.len();
+	let result = SearchResult {
+	    q: &q,
+	    sort: sort.as_str(),
+	    total,
+	    page: paging.page,
+	    per_page: paging.per_page,
+	    pages: paging.pages(total),
+	    hits: self.make_list(terms, paging, &mut hits, docs),
+	};
+	match serde_json::to_string(&result) {
+	    Ok(json) => json,
+	    Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+	}
+    }
+    // 表示するページの分だけ作る
+    fn make_list(&self, terms: &[String], paging: Paging, hits: &mut Vec<Hit>,
+		 docs: &HashMap<String, Doc>) -> Vec<ResultFile> {
+	let range = paging.range(hits.len());
 	let mut list = Vec::<ResultFile>::new();
-	for f in files {
-	    let rf = ResultFile::new(f);
+	for hit in hits.drain(range) {
+	    let doc = docs.get(&hit.path);
+	    let rf = ResultFile::new(hit, doc, terms, &self.urls, &self.decoder, &self.formats, self.stale.as_ref());
 	    list.push(rf);
 	}
+	list
+    }
+    pub fn make_html(&self, q: String, terms: &[String], sort: SortOrder, paging: Paging, mut hits: Vec<Hit>,
+		     docs: &HashMap<String, Doc>) -> String {
+	let total = hits.len();
+	let pages = paging.pages(total);
+	let range = paging.range(total);
+	let first = range.start + 1;
+	let last = range.end;
+	let list = self.make_list(terms, paging, &mut hits, docs);
+	let prev_url = if paging.page > 1 {
+	    Some(Self::make_query(&q, sort, paging.page.min(pages + 1) - 1, paging.per_page))
+	} else {
+	    None
+	};
+	let next_url = if paging.page < pages {
+	    Some(Self::make_query(&q, sort, paging.page + 1, paging.per_page))
+	} else {
+	    None
+	};
+	let sort_links = Self::make_sort_links(&q, sort, paging.per_page);
 	let mut ctxt = Context::new();
 	ctxt.insert("q", &q);
+	ctxt.insert("sort", sort.as_str());
+	ctxt.insert("sort_links", &sort_links);
+	ctxt.insert("total", &total);
+	ctxt.insert("page", &paging.page);
+	ctxt.insert("pages", &pages);
+	ctxt.insert("first", &first);
+	ctxt.insert("last", &last);
+	ctxt.insert("prev_url", &prev_url);
+	ctxt.insert("next_url", &next_url);
 	ctxt.insert("list", &list);
-	let html = match tera.render("index.html", &ctxt) {
-	    Ok(html) => html,
-	    Err(e) => return format!("{:?}", e),
+	self.render("index.html", &ctxt)
+    }
+    // 日記を一つ表示する。読めなければ None。
+    pub fn make_entry_html(&self, path: &String, q: Option<&String>, terms: &[String],
+			   docs: &HashMap<String, Doc>) -> Option<String> {
+	let (title, body) = read_entry(path, docs.get(path), &self.decoder, &self.formats).ok()?;
+	let title = docs.get(path).and_then(|doc| doc.title.clone()).unwrap_or(title);
+	let date = docs.get(path).and_then(|doc| doc.date).map(hits::format_date);
+	let query = match q {
+	    Some(q) => format!("?{}", url::form_urlencoded::Serializer::new(String::new()).append_pair("q", q).finish()),
+	    None => String::new(),
 	};
-	html
+	let link = |p: Option<&String>| p.and_then(|p| {
+	    let title = match docs.get(p).and_then(|doc| doc.title.clone()) {
+		Some(title) => title,
+		None => read_entry(p, docs.get(p), &self.decoder, &self.formats).ok()?.0,
+	    };
+	    let date = docs.get(p).and_then(|doc| doc.date).map(hits::format_date).unwrap_or_default();
+	    Some(EntryLink {
+		url: format!("{}{}", self.urls.entry_url(p), query),
+		title,
+		date,
+	    })
+	});
+	let (prev, next) = hits::neighbors(path, docs);
+
+	let mut ctxt = Context::new();
+	ctxt.insert("q", &q);
+	ctxt.insert("path", path);
+	let url = match docs.get(path).and_then(|doc| doc.uri.clone()) {
+	    Some(uri) => uri,
+	    None => self.urls.make_url(path, docs.get(path)),
+	};
+	ctxt.insert("url", &url);
+	ctxt.insert("title", &title);
+	ctxt.insert("date", &date);
+	ctxt.insert("body", &snippet::highlight(&body, terms));
+	ctxt.insert("prev", &link(prev));
+	ctxt.insert("next", &link(next));
+	Some(self.render("entry.html", &ctxt))
+    }
+    fn make_query(q: &str, sort: SortOrder, page: usize, per_page: usize) -> String {
+	let query = url::form_urlencoded::Serializer::new(String::new())
+	    .append_pair("q", q)
+	    .append_pair("sort", sort.as_str())
+	    .append_pair("page", &page.to_string())
+	    .append_pair("per_page", &per_page.to_string())
+	    .finish();
+	format!("?{}", query)
+    }
+    fn make_sort_links(q: &str, sort: SortOrder, per_page: usize) -> Vec<SortLink> {
+	let orders = [
+	    (SortOrder::Newest, "新しい順"),
+	    (SortOrder::Oldest, "古い順"),
+	    (SortOrder::Relevance, "関連度順"),
+	];
+	orders.iter().map(|(order, label)| {
+	    SortLink {
+		label,
+		url: Self::make_query(q, *order, 1, per_page),
+		current: *order == sort,
+	    }
+	}).collect()
     }
     fn make_internal_error(&self) -> String {
 	String::from("internal error")
@@ -105,12 +489,13 @@ mod tests {
 
     #[test]
     fn test() {
-	let res = Responder::new();
+	let res = Responder::new(None, None, false, UrlMapper::new("http://localhost/{stem}", &[])).unwrap();
 	let files = set!{
 	    String::from("/home/masm/esdiary/split/202208/20220805p01.est"),
 	    String::from("/home/masm/esdiary/split/202208/20220803p01.est")
 	};
-	let html = res.make_html(String::from("foo>bar"), 1, files);
+	let hits = files.into_iter().map(|path| Hit { path, date: None, score: 0.0 }).collect();
+	let html = res.make_html(String::from("foo>bar"), &[String::from("foo")], SortOrder::Newest, Paging::from_params(None, None), hits, &HashMap::new());
 	out(&html);
     }
 
@@ -119,4 +504,345 @@ mod tests {
 	file.write_all(s.as_bytes()).expect("write_all failed.");
 	file.flush().expect("flush failed.");
     }
+
+    #[test]
+    fn entry() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-responder-{}", std::process::id()));
+	std::fs::create_dir_all(dir.join("202208")).unwrap();
+	let mut docs = HashMap::<String, Doc>::new();
+	let mut paths = Vec::<String>::new();
+	for (name, date, text) in [("20220803p01.est", 20220803, "前の日\n雨。"),
+				   ("20220805p01.est", 20220805, "東京へ\n東京駅は<混んで>いた。\n帰った。"),
+				   ("20220810p01.est", 20220810, "次の日\n晴れ。")] {
+	    let path = dir.join("202208").join(name).to_str().unwrap().to_string();
+	    std::fs::write(&path, text).unwrap();
+	    docs.insert(path.clone(), Doc { date: Some(date), ..Default::default() });
+	    paths.push(path);
+	}
+
+	let res = Responder::new(None, None, false, UrlMapper::new("http://localhost/{stem}", &[dir.to_str().unwrap().to_string()])).unwrap();
+	let q = String::from("東京");
+	let html = res.make_entry_html(&paths[1], Some(&q), std::slice::from_ref(&q), &docs).unwrap();
+	assert!(html.contains("<h1>東京へ</h1>"));
+	assert!(html.contains("<mark>東京</mark>駅は&lt;混んで&gt;いた。\n帰った。"));
+	assert!(html.contains("href=\"/entry/202208/20220803p01.est?q=%E6%9D%B1%E4%BA%AC\""));
+	assert!(html.contains("href=\"/entry/202208/20220810p01.est?q=%E6%9D%B1%E4%BA%AC\""));
+	assert!(res.make_entry_html(&String::from("/nonexistent"), None, &[], &docs).is_none());
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn templates() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-templates-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let dir_s = dir.to_str().unwrap().to_string();
+	// 待たずに済むよう、更新時刻は書いた後で進めておく
+	let write = |text: &str, secs: u64| {
+	    let path = dir.join("index.html");
+	    std::fs::write(&path, text).unwrap();
+	    let file = std::fs::File::options().write(true).open(&path).unwrap();
+	    file.set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs)).unwrap();
+	};
+	write("custom {{ total }}", 1_660_000_000);
+
+	let res = Responder::new(Some(&dir_s), None, true, UrlMapper::new("", &[])).unwrap();
+	let paging = Paging::from_params(None, None);
+	assert_eq!(res.make_html(String::from("q"), &[], SortOrder::Newest, paging, vec![], &HashMap::new()), "custom 0");
+
+	// 更新時刻が変われば読み直す。壊れたテンプレートは無視する。
+	write("changed {{ total }}", 1_660_000_010);
+	assert_eq!(res.make_html(String::from("q"), &[], SortOrder::Newest, paging, vec![], &HashMap::new()), "changed 0");
+	write("broken {{ total ", 1_660_000_020);
+	assert_eq!(res.make_html(String::from("q"), &[], SortOrder::Newest, paging, vec![], &HashMap::new()), "changed 0");
+
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert!(Responder::new(Some(&dir_s), None, false, UrlMapper::new("", &[])).is_err());
+    }
+
+    #[test]
+    fn statics() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-static-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let dir_s = dir.to_str().unwrap().to_string();
+
+	let res = Responder::new(None, None, false, UrlMapper::new("", &[])).unwrap();
+	assert!(res.static_file("style.css").is_some());
+	assert!(res.static_file("nothing.css").is_none());
+
+	std::fs::write(dir.join("style.css"), "body {}").unwrap();
+	std::fs::write(dir.join("extra.css"), "p {}").unwrap();
+	let res = Responder::new(None, Some(&dir_s), false, UrlMapper::new("", &[])).unwrap();
+	assert_eq!(res.static_file("style.css").unwrap(), b"body {}");
+	assert_eq!(res.static_file("extra.css").unwrap(), b"p {}");
+
+	let stats = Stats { docs: 2, words: 3, first_date: None, last_date: None };
+	let html = res.make_home_html(&stats);
+	assert!(html.contains("<form method=\"get\" action=\"/\">"));
+	assert!(html.contains("2 件の日記、3 語"));
+	assert!(res.make_error_html(404, "Not Found").contains("<h1>404 Not Found</h1>"));
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-missing-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let dir_s = dir.to_str().unwrap().to_string();
+	let present = dir.join("20220805p01.est").to_str().unwrap().to_string();
+	let gone = dir.join("20220806p01.est").to_str().unwrap().to_string();
+	std::fs::write(&present, b"\xb5\xfe\xc5\xd4\n\xc5\xec\xb5\xfe\xa4\xd8").unwrap();	// EUC-JP
+
+	let mut res = Responder::new(None, None, false, UrlMapper::new("", &[])).unwrap();
+	res.schedule_pruning(StaleList::new(&dir_s));
+	let hits = vec![
+	    Hit { path: present.clone(), date: None, score: 0.0 },
+	    Hit { path: gone.clone(), date: None, score: 0.0 },
+	];
+	let html = res.make_html(String::from("東京"), &[], SortOrder::Newest, Paging::from_params(None, None), hits, &HashMap::new());
+	assert!(html.contains("20220806p01.est</a>"));
+	assert_eq!(html.matches("(ファイルが読めません)").count(), 1);
+	assert!(html.contains(">京都</a>"));
+	let stale = std::fs::read_to_string(dir.join("index.stale.txt")).unwrap();
+	assert_eq!(stale, format!("{}\n", gone));
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stored() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-stored-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let path = dir.join("20220805p01.est").to_str().unwrap().to_string();
+	std::fs::write(&path, "").unwrap();
+	let mut docs = HashMap::<String, Doc>::new();
+	docs.insert(path.clone(), Doc {
+	    date: Some(20220805),
+	    title: Some(String::from("東京へ")),
+	    length: Some(14),
+	    excerpt: Some(String::from("駅は混んでいた。\n東京から帰った。")),
+	    uri: Some(String::from("http://example.com/20220805")),
+	    attrs: vec![(String::from("mdate"), String::from("2022-08-06"))],
+	});
+	let res = Responder::new(None, None, false, UrlMapper::new("", &[])).unwrap();
+	let hits = vec![Hit { path: path.clone(), date: Some(20220805), score: 0.0 }];
+	let terms = [String::from("東京")];
+	let json = res.make_json(String::from("東京"), &terms, SortOrder::Newest, Paging::from_params(None, None), hits, &docs);
+	let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+	let hit = &json["hits"][0];
+	assert_eq!(hit["title"], "東京へ");
+	assert_eq!(hit["snippet"], "東京から帰った。");
+	assert_eq!(hit["length"], 14);
+	assert_eq!(hit["url"], "http://example.com/20220805");
+	assert_eq!(hit["attrs"]["mdate"], "2022-08-06");
+	assert_eq!(hit["missing"], false);
+
+	// 文書の URL は属性値としてエスケープする
+	docs.get_mut(&path).unwrap().uri = Some(String::from("http://example.com/\" onmouseover=\"alert(1)"));
+	let hits = vec![Hit { path: path.clone(), date: Some(20220805), score: 0.0 }];
+	let html = res.make_html(String::from("東京"), &terms, SortOrder::Newest, Paging::from_params(None, None), hits, &docs);
+	assert!(!html.contains("\" onmouseover"));
+	assert!(html.contains("&quot; onmouseover"));
+
+	// 題名と抜粋があっても、消えたファイルは missing にして刈り取りに回す
+	std::fs::remove_file(&path).unwrap();
+	let mut res = Responder::new(None, None, false, UrlMapper::new("", &[])).unwrap();
+	res.schedule_pruning(StaleList::new(dir.to_str().unwrap()));
+	let hits = vec![Hit { path: path.clone(), date: Some(20220805), score: 0.0 }];
+	let json = res.make_json(String::from("東京"), &terms, SortOrder::Newest, Paging::from_params(None, None), hits, &docs);
+	let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+	assert_eq!(json["hits"][0]["title"], "東京へ");
+	assert_eq!(json["hits"][0]["missing"], true);
+	let stale = std::fs::read_to_string(dir.join("index.stale.txt")).unwrap();
+	assert_eq!(stale, format!("{}\n", path));
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncated() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-truncated-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let path = dir.join("20220805.txt").to_str().unwrap().to_string();
+	let body = format!("{}\n京都へ行った。", "雨。".repeat(300));
+	std::fs::write(&path, format!("題名\n{}\n", body)).unwrap();
+	let mut docs = HashMap::<String, Doc>::new();
+	docs.insert(path.clone(), Doc {
+	    date: Some(20220805),
+	    title: Some(String::from("題名")),
+	    length: Some(body.chars().count()),
+	    excerpt: Some(body.chars().take(400).collect()),
+	    ..Default::default()
+	});
+	let res = Responder::new(None, None, false, UrlMapper::new("", &[])).unwrap();
+	let hits = vec![Hit { path: path.clone(), date: Some(20220805), score: 0.0 }];
+	let terms = [String::from("京都")];
+	let json = res.make_json(String::from("京都"), &terms, SortOrder::Newest, Paging::from_params(None, None), hits, &docs);
+	let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+	assert_eq!(json["hits"][0]["title"], "題名");
+	assert_eq!(json["hits"][0]["snippet"], "京都へ行った。");
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn draft() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-draft-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let file = dir.join("20220805p01.est").to_str().unwrap().to_string();
+	std::fs::write(&file, "@title = 東京へ\n@cdate=2022-08-05\n%VECTOR\n\n駅は混んでいた。\n\t隠し\n帰った。\n").unwrap();
+	let decoder = Decoder::new(&HashMap::new()).unwrap();
+	let formats = Formats::new(&HashMap::new()).unwrap();
+	let (title, body) = read_entry(&file, None, &decoder, &formats).unwrap();
+	assert_eq!(title, "東京へ");
+	assert_eq!(body, "駅は混んでいた。\n帰った。");
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn section() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-section-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let file = dir.join("2022-08.org").to_str().unwrap().to_string();
+	std::fs::write(&file, "#+TITLE: 2022年8月\n* 2022-08-05 東京へ\n東京駅は混んでいた。\n[[https://example.com/][地図]]\n* 2022-08-06\n雨。\n").unwrap();
+	let path = format!("{}#2022-08-05", file);
+	let mut docs = HashMap::<String, Doc>::new();
+	docs.insert(path.clone(), Doc {
+	    date: Some(20220805),
+	    title: Some(String::from("2022-08-05 東京へ")),
+	    attrs: vec![(String::from("lines"), String::from("2-4"))],
+	    ..Default::default()
+	});
+
+	let res = Responder::new(None, None, false, UrlMapper::new("file://{path}", &[dir.to_str().unwrap().to_string()])).unwrap();
+	let html = res.make_entry_html(&path, None, &[], &docs).unwrap();
+	assert!(html.contains("<h1>2022-08-05 東京へ</h1>"));
+	assert!(html.contains("東京駅は混んでいた。"));
+	assert!(!html.contains("雨。"));
+	assert!(html.contains("地図") && !html.contains("[["));
+	assert!(html.contains("2022-08.org#2022-08-05\""));
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn markdown() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-markdown-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let path = dir.join("2022-08-05.md").to_str().unwrap().to_string();
+	std::fs::write(&path, "---\ntitle: 東京へ\ntags: [旅行]\n---\n\n**東京駅**は[混んで](http://example.com/)いた。\n").unwrap();
+	let mut docs = HashMap::<String, Doc>::new();
+	docs.insert(path.clone(), Doc { date: Some(20220805), ..Default::default() });
+
+	// front matter と記法は本文に出さない
+	let res = Responder::new(None, None, false, UrlMapper::new("", &[])).unwrap();
+	let html = res.make_entry_html(&path, None, &[], &docs).unwrap();
+	assert!(html.contains("<h1>東京へ</h1>"));
+	assert!(html.contains("東京駅は混んでいた。"));
+	assert!(!html.contains("title:"));
+	assert!(!html.contains("**"));
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn html() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-html-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let path = dir.join("20220805.html").to_str().unwrap().to_string();
+	std::fs::write(&path, "<html><head><title>東京へ</title><script>var menu = 1;</script></head>\n\
+			       <body><nav>前の日</nav><p>東京駅は<b>混んで</b>いた。</p></body></html>\n").unwrap();
+	let mut docs = HashMap::<String, Doc>::new();
+	docs.insert(path.clone(), Doc { date: Some(20220805), ..Default::default() });
+
+	// ページのソースではなく、rsdiary_add が索引に入れたのと同じ本文を出す
+	let res = Responder::new(None, None, false, UrlMapper::new("", &[])).unwrap();
+	let html = res.make_entry_html(&path, None, &[], &docs).unwrap();
+	assert!(html.contains("<h1>東京へ</h1>"));
+	assert!(html.contains("東京駅は混んでいた。"));
+	assert!(!html.contains("&lt;p&gt;"));
+	assert!(!html.contains("var menu") && !html.contains("前の日"));
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn tdiary() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-tdiary-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let file = dir.join("202208.td2").to_str().unwrap().to_string();
+	std::fs::write(&file, "TDIARY2.01.00\n\
+			       Date: 20220805\n\
+			       Title: 東京へ\n\
+			       Format: Wiki\n\
+			       \n\
+			       ! 東京駅\n\
+			       [[混んで|https://example.com/]]いた。{{amazon '4000000000'}}\n\
+			       ..で始まる行\n\
+			       .\n").unwrap();
+	let path = format!("{}#2022-08-05", file);
+	let doc = Doc {
+	    date: Some(20220805),
+	    title: Some(String::from("東京へ")),
+	    attrs: vec![(String::from("lines"), String::from("5-8")), (String::from("format"), String::from("wiki"))],
+	    ..Default::default()
+	};
+	let decoder = Decoder::new(&HashMap::new()).unwrap();
+	let formats = Formats::new(&HashMap::new()).unwrap();
+	let (_, body) = read_entry(&path, Some(&doc), &decoder, &formats).unwrap();
+	assert_eq!(body, "東京駅\n混んでいた。\n.で始まる行");
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn mail() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-mail-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let file = dir.join("notes.mbox").to_str().unwrap().to_string();
+	// 2通目の本文は「東京駅は混んでいた。」の ISO-2022-JP
+	std::fs::write(&file, "From me@example.com Thu Aug  4 10:00:00 2022\n\
+			       Subject: memo\n\
+			       \n\
+			       雨。\n\
+			       \n\
+			       From me@example.com Fri Aug  5 10:00:00 2022\n\
+			       Subject: =?ISO-2022-JP?B?GyRCRWw1fiRYGyhC?=\n\
+			       Content-Type: text/plain; charset=ISO-2022-JP\n\
+			       Content-Transfer-Encoding: base64\n\
+			       \n\
+			       GyRCRWw1fjFYJE86LiRzJEckJCQ/ISMbKEIK\n").unwrap();
+	let path = format!("{}#m2", file);
+	let doc = Doc {
+	    date: Some(20220805),
+	    title: Some(String::from("東京へ")),
+	    attrs: vec![(String::from("format"), String::from("mail")), (String::from("lines"), String::from("6-11"))],
+	    ..Default::default()
+	};
+	let decoder = Decoder::new(&HashMap::new()).unwrap();
+	let formats = Formats::new(&HashMap::new()).unwrap();
+	let (title, body) = read_entry(&path, Some(&doc), &decoder, &formats).unwrap();
+	assert_eq!(title, "東京へ");
+	assert_eq!(body, "東京駅は混んでいた。");
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn filtered() {
+	let dir = std::env::temp_dir().join(format!("rsdiary-filtered-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let file = dir.join("20220805.pdf").to_str().unwrap().to_string();
+	let cache = dir.join("0123.txt").to_str().unwrap().to_string();
+	std::fs::write(&file, b"%PDF-1.4\n\xe2\x80").unwrap();
+	std::fs::write(&cache, "東京へ\n東京駅は混んでいた。\n").unwrap();
+	let doc = Doc {
+	    date: Some(20220805),
+	    attrs: vec![(String::from("filtered"), cache)],
+	    ..Default::default()
+	};
+	let decoder = Decoder::new(&HashMap::new()).unwrap();
+	let formats = Formats::new(&HashMap::new()).unwrap();
+	let (title, body) = read_entry(&file, Some(&doc), &decoder, &formats).unwrap();
+	assert_eq!(title, "東京へ");
+	assert_eq!(body, "東京駅は混んでいた。");
+
+	std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
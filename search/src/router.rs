@@ -0,0 +1,74 @@
+// URL と処理の対応
+// GET /                 検索フォーム。q があれば検索結果
+// GET /api/search       検索結果の JSON
+// GET /entry/<rel>      日記を一つ表示
+// GET /static/<name>    CSS など
+
+#[derive(PartialEq, Debug)]
+pub enum Route<'a> {
+    Home,
+    Api,
+    Entry(&'a str),
+    Static(&'a str),
+    NotFound,
+    MethodNotAllowed,
+}
+
+pub fn route<'a>(method: &str, path: &'a str) -> Route<'a> {
+    let route = if path == "/" {
+	Route::Home
+    } else if path == "/api/search" {
+	Route::Api
+    } else if let Some(id) = path.strip_prefix("/entry/").filter(|id| !id.is_empty()) {
+	Route::Entry(id)
+    } else if let Some(name) = path.strip_prefix("/static/").filter(|name| is_plain_name(name)) {
+	Route::Static(name)
+    } else {
+	Route::NotFound
+    };
+    if route != Route::NotFound && method != "GET" && method != "HEAD" {
+	return Route::MethodNotAllowed;
+    }
+    route
+}
+
+// ディレクトリの外に出られないよう、ファイル名だけを許す
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty()
+	&& !name.starts_with('.')
+	&& name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+pub fn content_type(name: &str) -> &'static str {
+    match name.rsplit_once('.').map(|(_, ext)| ext) {
+	Some("css") => "text/css; charset=utf-8",
+	Some("js") => "text/javascript; charset=utf-8",
+	Some("png") => "image/png",
+	Some("svg") => "image/svg+xml",
+	Some("ico") => "image/x-icon",
+	_ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	assert_eq!(route("GET", "/"), Route::Home);
+	assert_eq!(route("HEAD", "/"), Route::Home);
+	assert_eq!(route("GET", "/api/search"), Route::Api);
+	assert_eq!(route("GET", "/entry/202208/20220805p01.est"), Route::Entry("202208/20220805p01.est"));
+	assert_eq!(route("GET", "/entry/"), Route::NotFound);
+	assert_eq!(route("GET", "/static/style.css"), Route::Static("style.css"));
+	assert_eq!(route("GET", "/static/../index.txt"), Route::NotFound);
+	assert_eq!(route("GET", "/static/.hidden"), Route::NotFound);
+	assert_eq!(route("GET", "/favicon.ico"), Route::NotFound);
+	assert_eq!(route("POST", "/"), Route::MethodNotAllowed);
+	assert_eq!(route("POST", "/nowhere"), Route::NotFound);
+
+	assert_eq!(content_type("style.css"), "text/css; charset=utf-8");
+	assert_eq!(content_type("README"), "application/octet-stream");
+    }
+}
@@ -0,0 +1,201 @@
+// 検索語の周辺を切り出して要約を作る
+
+use std::cmp::Reverse;
+
+const MAX_FRAGMENTS: usize = 3;
+const FRAGMENT_LEN: usize = 80;	// 文字数
+const ELLIPSIS: &str = "…";
+pub const SEPARATOR: &str = " … ";	// 断片の区切り
+
+pub fn escape_html(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    for c in s.chars() {
+	match c {
+	    '&' => buf.push_str("&amp;"),
+	    '<' => buf.push_str("&lt;"),
+	    '>' => buf.push_str("&gt;"),
+	    '"' => buf.push_str("&quot;"),
+	    '\'' => buf.push_str("&#39;"),
+	    _ => buf.push(c),
+	}
+    }
+    buf
+}
+
+// 助詞などの1文字のかなは、他に検索語があればハイライトしない。
+// 長い語から順に照合したいので、長さの降順に並べて返す。
+fn effective_terms(terms: &[String]) -> Vec<Vec<char>> {
+    let is_trivial = |t: &String| {
+	let mut chars = t.chars();
+	match (chars.next(), chars.next()) {
+	    (Some(c), None) => ('\u{3040}'..='\u{30ff}').contains(&c) || !c.is_alphanumeric(),
+	    _ => false,
+	}
+    };
+    let mut list: Vec<&String> = terms.iter().filter(|t| !t.is_empty() && !is_trivial(t)).collect();
+    if list.is_empty() {
+	list = terms.iter().filter(|t| !t.is_empty()).collect();
+    }
+    let mut list: Vec<Vec<char>> = list.iter().map(|t| t.chars().collect()).collect();
+    list.sort_by_key(|t| Reverse(t.len()));
+    list.dedup();
+    list
+}
+
+// 文字単位で、各位置から始まる検索語の長さを返す
+fn find_at(text: &[char], pos: usize, terms: &[Vec<char>]) -> Option<usize> {
+    terms.iter()
+	.find(|t| text[pos..].starts_with(t))
+	.map(|t| t.len())
+}
+
+// 要約に出せる検索語が text にあるか
+pub fn matches(text: &str, terms: &[String]) -> bool {
+    effective_terms(terms).iter().any(|t| text.contains(&t.iter().collect::<String>()))
+}
+
+// 句点や改行で文に分ける。句点は前の文に含める。
+fn sentences(body: &str) -> Vec<Vec<char>> {
+    let mut list = Vec::<Vec<char>>::new();
+    let mut cur = Vec::<char>::new();
+    for c in body.chars() {
+	match c {
+	    '\n' | '\r' => {
+		list.push(cur);
+		cur = Vec::<char>::new();
+	    },
+	    '。' | '！' | '？' | '!' | '?' => {
+		cur.push(c);
+		list.push(cur);
+		cur = Vec::<char>::new();
+	    },
+	    '\t' => cur.push(' '),
+	    _ => cur.push(c),
+	}
+    }
+    list.push(cur);
+    list.into_iter()
+	.map(|s| {
+	    let s: String = s.into_iter().collect();
+	    s.trim().chars().collect()
+	})
+	.filter(|s: &Vec<char>| !s.is_empty())
+	.collect()
+}
+
+// 長すぎる文は最初に一致した所の少し前から FRAGMENT_LEN 文字だけにする
+fn clip(sentence: &[char], first_match: usize) -> String {
+    if sentence.len() <= FRAGMENT_LEN {
+	return sentence.iter().collect();
+    }
+    let start = first_match.saturating_sub(FRAGMENT_LEN / 4).min(sentence.len() - FRAGMENT_LEN);
+    let end = start + FRAGMENT_LEN;
+    let mut buf = String::new();
+    if start > 0 {
+	buf.push_str(ELLIPSIS);
+    }
+    buf.extend(sentence[start..end].iter());
+    if end < sentence.len() {
+	buf.push_str(ELLIPSIS);
+    }
+    buf
+}
+
+// 検索語を含む文を前から最大 MAX_FRAGMENTS 個。一つも無ければ本文の先頭。
+pub fn fragments(body: &str, terms: &[String]) -> Vec<String> {
+    let terms = effective_terms(terms);
+    let sentences = sentences(body);
+    let mut list = Vec::<String>::new();
+    for s in sentences.iter() {
+	if let Some(pos) = (0..s.len()).find(|pos| find_at(s, *pos, &terms).is_some()) {
+	    list.push(clip(s, pos));
+	    if list.len() >= MAX_FRAGMENTS {
+		break;
+	    }
+	}
+    }
+    if list.is_empty() {
+	let mut buf = Vec::<char>::new();
+	for s in sentences.iter() {
+	    if !buf.is_empty() {
+		buf.push(' ');
+	    }
+	    buf.extend(s.iter());
+	    if buf.len() >= FRAGMENT_LEN {
+		break;
+	    }
+	}
+	if !buf.is_empty() {
+	    list.push(clip(&buf, 0));
+	}
+    }
+    list
+}
+
+// HTML エスケープした上で、検索語を <mark> で囲む
+pub fn highlight(text: &str, terms: &[String]) -> String {
+    let terms = effective_terms(terms);
+    let text: Vec<char> = text.chars().collect();
+    let mut buf = String::new();
+    let mut plain = String::new();
+    let mut pos = 0;
+    while pos < text.len() {
+	match find_at(&text, pos, &terms) {
+	    Some(len) => {
+		buf.push_str(&escape_html(&plain));
+		plain.clear();
+		let word: String = text[pos..pos + len].iter().collect();
+		buf.push_str("<mark>");
+		buf.push_str(&escape_html(&word));
+		buf.push_str("</mark>");
+		pos += len;
+	    },
+	    None => {
+		plain.push(text[pos]);
+		pos += 1;
+	    },
+	}
+    }
+    buf.push_str(&escape_html(&plain));
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(list: &[&str]) -> Vec<String> {
+	list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test() {
+	let body = "朝は雨だった。\n昼から東京へ行った。東京駅は混んでいた！\n夜は<b>家</b>で & 休んだ。";
+
+	assert_eq!(escape_html("<a href=\"x\">&'"), "&lt;a href=&quot;x&quot;&gt;&amp;&#39;");
+
+	assert_eq!(fragments(body, &terms(&["東京", "は"])),
+		   vec!["昼から東京へ行った。", "東京駅は混んでいた！"]);
+	assert_eq!(fragments(body, &terms(&["は"])),
+		   vec!["朝は雨だった。", "東京駅は混んでいた！", "夜は<b>家</b>で & 休んだ。"]);
+	assert_eq!(fragments(body, &terms(&["大阪"])),
+		   vec!["朝は雨だった。 昼から東京へ行った。 東京駅は混んでいた！ 夜は<b>家</b>で & 休んだ。"]);
+
+	assert_eq!(highlight("夜は<b>家</b>で & 休んだ。", &terms(&["家", "は"])),
+		   "夜は&lt;b&gt;<mark>家</mark>&lt;/b&gt;で &amp; 休んだ。");
+	assert_eq!(highlight("東京駅", &terms(&["東京", "東京駅"])),
+		   "<mark>東京駅</mark>");
+    }
+
+    #[test]
+    fn long() {
+	let long = "あ".repeat(100) + "東京" + &"い".repeat(100);
+	let frags = fragments(&long, &terms(&["東京"]));
+	assert_eq!(frags.len(), 1);
+	let chars: Vec<char> = frags[0].chars().collect();
+	assert_eq!(chars.len(), FRAGMENT_LEN + 2);
+	assert!(frags[0].starts_with(ELLIPSIS));
+	assert!(frags[0].ends_with(ELLIPSIS));
+	assert!(frags[0].contains("東京"));
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+use crate::index::{self, Doc};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+// URL の雛形に使えるもの
+// {path}  索引に登録されたパス
+// {rel}   roots からの相対パス (どの root の下にも無ければ {path} と同じ)
+// {name}  ファイル名
+// {stem}  拡張子を除いたファイル名
+// {yyyy} {mm} {dd}  文書の日付
+// 値は percent-encoding する。{path} と {rel} の `/` はそのまま。
+// 見出しで分けた文書 (path#anchor) は、ファイルの URL の後ろに #anchor を付ける。
+
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+pub struct UrlMapper {
+    template: String,
+    roots: Vec<String>,
+}
+
+impl UrlMapper {
+    pub fn new(template: &str, roots: &[String]) -> Self {
+	UrlMapper {
+	    template: template.to_string(),
+	    roots: roots.iter().map(|r| r.trim_end_matches('/').to_string()).collect(),
+	}
+    }
+
+    pub fn relative<'a>(&self, path: &'a str) -> &'a str {
+	for root in self.roots.iter() {
+	    if let Some(rel) = path.strip_prefix(root.as_str()) {
+		if let Some(rel) = rel.strip_prefix('/') {
+		    return rel;
+		}
+	    }
+	}
+	path.trim_start_matches('/')
+    }
+
+    // 内蔵の表示ページ
+    pub fn entry_url(&self, path: &str) -> String {
+	format!("/entry/{}", encode_path(self.relative(path)))
+    }
+
+    // entry_url() の逆。索引に無ければ None。
+    pub fn find_entry<'a, I>(&self, id: &str, paths: I) -> Option<&'a String>
+    where I: Iterator<Item = &'a String> {
+	let rel = percent_decode_str(id).decode_utf8().ok()?;
+	paths.into_iter().find(|p| self.relative(p) == rel)
+    }
+
+    pub fn make_url(&self, path: &str, doc: Option<&Doc>) -> String {
+	let (path, anchor) = index::split_anchor(path, doc);
+	let date = doc.and_then(|doc| doc.date);
+	let p = Path::new(path);
+	let name = p.file_name().and_then(|s| s.to_str()).unwrap_or("");
+	let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+	let (yyyy, mm, dd) = match date {
+	    Some(d) => (format!("{:04}", d / 10000), format!("{:02}", d / 100 % 100), format!("{:02}", d % 100)),
+	    None => (String::new(), String::new(), String::new()),
+	};
+
+	// 置換結果をもう一度置換しないよう、雛形を先頭から読んでいく
+	let mut buf = String::new();
+	let mut rest = &self.template[..];
+	while let Some(start) = rest.find('{') {
+	    buf.push_str(&rest[..start]);
+	    rest = &rest[start..];
+	    let end = match rest.find('}') {
+		Some(end) => end,
+		None => break,
+	    };
+	    let value = match &rest[1..end] {
+		"path" => encode_path(path.trim_start_matches('/')),
+		"rel" => encode_path(self.relative(path)),
+		"name" => encode(name),
+		"stem" => encode(stem),
+		"yyyy" => yyyy.clone(),
+		"mm" => mm.clone(),
+		"dd" => dd.clone(),
+		_ => rest[..end + 1].to_string(),	// 知らないものはそのまま
+	    };
+	    buf.push_str(&value);
+	    rest = &rest[end + 1..];
+	}
+	buf.push_str(rest);
+	if let Some(anchor) = anchor {
+	    if !buf.contains('#') {
+		buf.push('#');
+		buf.push_str(&encode(anchor));
+	    }
+	}
+	buf
+    }
+}
+
+fn encode(s: &str) -> String {
+    utf8_percent_encode(s, SEGMENT).to_string()
+}
+
+fn encode_path(s: &str) -> String {
+    s.split('/').map(encode).collect::<Vec<String>>().join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let roots = vec![String::from("/home/masm/esdiary/split/")];
+	let path = "/home/masm/esdiary/split/202208/20220805p01.est";
+	let dated = Doc { date: Some(20220805), ..Default::default() };
+	let section = Doc { date: Some(20220805), attrs: vec![(String::from("lines"), String::from("3-6"))], ..Default::default() };
+
+	let urls = UrlMapper::new("http://localhost/{stem}", &roots);
+	assert_eq!(urls.make_url(path, Some(&dated)), "http://localhost/20220805p01");
+
+	let urls = UrlMapper::new("https://example.com/diary/{rel}", &roots);
+	assert_eq!(urls.make_url(path, None), "https://example.com/diary/202208/20220805p01.est");
+
+	let urls = UrlMapper::new("/{yyyy}/{mm}/{dd}/{name}#{unknown}", &roots);
+	assert_eq!(urls.make_url(path, Some(&dated)), "/2022/08/05/20220805p01.est#{unknown}");
+
+	let urls = UrlMapper::new("file:///{path}", &roots);
+	assert_eq!(urls.make_url("/tmp/日記 1.txt", None), "file:///tmp/%E6%97%A5%E8%A8%98%201.txt");
+
+	let urls = UrlMapper::new("https://example.com/diary/{rel}", &roots);
+	assert_eq!(urls.make_url("/home/masm/esdiary/split/2022-08.org#2022-08-05", Some(&section)),
+		   "https://example.com/diary/2022-08.org#2022-08-05");
+	assert_eq!(urls.make_url("/home/masm/esdiary/split/C#メモ.txt", Some(&dated)),
+		   "https://example.com/diary/C%23%E3%83%A1%E3%83%A2.txt");
+
+	let urls = UrlMapper::new("", &roots);
+	assert_eq!(urls.entry_url("/home/masm/esdiary/split/2022-08.org#2022-08-05"), "/entry/2022-08.org%232022-08-05");
+	assert_eq!(urls.entry_url("/home/masm/esdiary/split/202208/日記.txt"), "/entry/202208/%E6%97%A5%E8%A8%98.txt");
+	let paths = [String::from(path), String::from("/home/masm/esdiary/split/202208/日記.txt")];
+	assert_eq!(urls.find_entry("202208/%E6%97%A5%E8%A8%98.txt", paths.iter()), Some(&paths[1]));
+	assert_eq!(urls.find_entry("202208/20220805p01.est", paths.iter()), Some(&paths[0]));
+	assert_eq!(urls.find_entry("202208/none.est", paths.iter()), None);
+
+	let urls = UrlMapper::new("/view?f={rel}", &roots);
+	assert_eq!(urls.make_url("/other/a{stem}.txt", None), "/view?f=other/a%7Bstem%7D.txt");
+    }
+}
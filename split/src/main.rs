@@ -0,0 +1,241 @@
+mod parse;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use parse::{Format, Parser, Part};
+
+// 1つのファイルに書き溜めた日記を、1日1件ずつ OUT/YYYYMM/YYYYMMDDpNN.est に分ける。
+// 内容が変わったファイルだけ書き直し、そのパスを標準出力に出すので
+//   rsdiary_split --out split ChangeLog | xargs rsdiary_add
+// で索引を更新できる。
+// どの元ファイルからどれを作ったかを OUT/.rsdiary_split に覚えておき、
+// 元から消えた日の分は削除する。
+
+const USAGE: &str = "usage: rsdiary_split --out DIR [--format changelog|headings] FILE...";
+const MANIFEST: &str = ".rsdiary_split";
+
+struct Options {
+    out: String,
+    format: Option<Format>,
+    files: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut out = env::var("RSDIARY_SPLIT_DIR").ok();
+    let mut format = None;
+    let mut files = Vec::<String>::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+	match arg.as_str() {
+	    "-o" | "--out" => {
+		out = Some(iter.next().ok_or(String::from(USAGE))?.clone());
+	    },
+	    "-f" | "--format" => {
+		format = match iter.next().map(|s| s.as_str()) {
+		    Some("changelog") => Some(Format::ChangeLog),
+		    Some("headings") => Some(Format::Headings),
+		    Some(s) => return Err(format!("unknown format: {}", s)),
+		    None => return Err(String::from(USAGE)),
+		};
+	    },
+	    "--" => files.extend(iter.by_ref().cloned()),
+	    s if s.starts_with('-') && s.len() > 1 => {
+		return Err(format!("unknown option: {}\n{}", s, USAGE));
+	    },
+	    s => files.push(s.to_string()),
+	}
+    }
+    let out = out.ok_or(String::from(USAGE))?;
+    if files.is_empty() {
+	return Err(String::from(USAGE));
+    }
+    Ok(Options { out, format, files })
+}
+
+// 同じ日の中で出てきた順に p01, p02, ... を振る
+fn name_parts(parts: &[Part]) -> Vec<(String, String)> {
+    let mut count = HashMap::<u32, usize>::new();
+    parts.iter().map(|part| {
+	let n = count.entry(part.date).or_insert(0);
+	*n += 1;
+	let rel = format!("{:06}/{:08}p{:02}.est", part.date / 100, part.date, n);
+	(rel, render(part))
+    }).collect()
+}
+
+// Hyper Estraier のドラフト形式。TAB で始まる行は隠しテキストになるので空白にする。
+fn render(part: &Part) -> String {
+    let mut buf = format!("@title={}\n@cdate={:04}-{:02}-{:02}\n\n",
+			  part.title, part.date / 10000, part.date / 100 % 100, part.date % 100);
+    for line in part.body.lines() {
+	let text = line.trim_start_matches('\t');
+	let tabs = line.len() - text.len();
+	buf.push_str(&"    ".repeat(tabs));
+	buf.push_str(text);
+	buf.push('\n');
+    }
+    buf
+}
+
+// rel を source のものにする。別の元ファイルから作ったものなら、その元ファイルを返す。
+fn claim(owners: &mut HashMap<String, String>, rel: &str, source: &str) -> Result<(), String> {
+    match owners.get(rel) {
+	Some(other) if other != source => Err(other.clone()),
+	_ => {
+	    owners.insert(rel.to_string(), source.to_string());
+	    Ok(())
+	},
+    }
+}
+
+// 元ファイル -> 作ったファイル (OUT からの相対パス)
+fn read_manifest(path: &Path) -> BTreeMap<String, Vec<String>> {
+    let mut map = BTreeMap::<String, Vec<String>>::new();
+    if let Ok(buf) = fs::read_to_string(path) {
+	for line in buf.lines() {
+	    if let Some((source, rel)) = line.split_once('\t') {
+		map.entry(source.to_string()).or_default().push(rel.to_string());
+	    }
+	}
+    }
+    map
+}
+
+fn write_manifest(path: &Path, map: &BTreeMap<String, Vec<String>>) -> std::io::Result<()> {
+    let mut buf = String::new();
+    for (source, rels) in map.iter() {
+	for rel in rels.iter() {
+	    buf.push_str(&format!("{}\t{}\n", source, rel));
+	}
+    }
+    let tmp = path.with_extension("new");
+    fs::write(&tmp, buf)?;
+    fs::rename(&tmp, path)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+	Ok(opts) => opts,
+	Err(msg) => {
+	    eprintln!("{}", msg);
+	    process::exit(2);
+	},
+    };
+    let out = PathBuf::from(&opts.out);
+    let manifest_path = out.join(MANIFEST);
+    let mut manifest = read_manifest(&manifest_path);
+    let parser = Parser::new();
+
+    let (mut written, mut unchanged, mut removed) = (0, 0, 0);
+    let mut failed = false;
+    // 作ったファイル -> 元ファイル。前回の分も入れておき、別の元ファイルの分は上書きしない。
+    let mut owners: HashMap<String, String> = manifest.iter()
+	.flat_map(|(source, rels)| rels.iter().map(move |rel| (rel.clone(), source.clone())))
+	.collect();
+    for file in opts.files.iter() {
+	let buf = match fs::read_to_string(file) {
+	    Ok(buf) => buf,
+	    Err(why) => {
+		eprintln!("couldn't read {}: {}", file, why);
+		failed = true;
+		continue;
+	    },
+	};
+	let source = fs::canonicalize(file).map_or(file.clone(), |p| p.to_string_lossy().to_string());
+	let format = opts.format.unwrap_or_else(|| parser.detect(&buf));
+	let parts = parser.parse(&buf, format);
+	if parts.is_empty() {
+	    eprintln!("{}: no dated entries", file);
+	}
+
+	let mut rels = Vec::<String>::new();
+	for (rel, content) in name_parts(&parts) {
+	    if let Err(other) = claim(&mut owners, &rel, &source) {
+		eprintln!("{}: {} is already made from {}, not written", file, rel, other);
+		failed = true;
+		continue;
+	    }
+	    let path = out.join(&rel);
+	    if fs::read_to_string(&path).is_ok_and(|old| old == content) {
+		unchanged += 1;
+	    } else {
+		let result = fs::create_dir_all(path.parent().unwrap()).and_then(|_| fs::write(&path, &content));
+		match result {
+		    Ok(()) => {
+			println!("{}", path.display());
+			written += 1;
+		    },
+		    Err(why) => {
+			eprintln!("couldn't write {}: {}", path.display(), why);
+			failed = true;
+		    },
+		}
+	    }
+	    rels.push(rel);
+	}
+
+	// 前回作ったのに今回は無いもの
+	let keep: HashSet<&String> = rels.iter().collect();
+	for rel in manifest.get(&source).into_iter().flatten() {
+	    if keep.contains(rel) || owners.get(rel).is_some_and(|owner| owner != &source) {
+		continue;
+	    }
+	    owners.remove(rel);
+	    let path = out.join(rel);
+	    match fs::remove_file(&path) {
+		Ok(()) => {
+		    eprintln!("removed {}", path.display());
+		    removed += 1;
+		},
+		Err(why) if why.kind() == std::io::ErrorKind::NotFound => {},
+		Err(why) => eprintln!("couldn't remove {}: {}", path.display(), why),
+	    }
+	}
+	manifest.insert(source, rels);
+    }
+
+    if let Err(why) = fs::create_dir_all(&out).and_then(|_| write_manifest(&manifest_path, &manifest)) {
+	eprintln!("couldn't write {}: {}", manifest_path.display(), why);
+	failed = true;
+    }
+    eprintln!("{} written, {} unchanged, {} removed", written, unchanged, removed);
+    if failed {
+	process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+	let parts = vec![
+	    Part { date: 20220805, title: String::from("東京へ"), body: String::from("東京駅は\n\tコード") },
+	    Part { date: 20220806, title: String::from("雨"), body: String::from("") },
+	    Part { date: 20220805, title: String::from("帰った"), body: String::from("夜。") },
+	];
+	let named = name_parts(&parts);
+	let rels: Vec<&str> = named.iter().map(|(rel, _)| rel.as_str()).collect();
+	assert_eq!(rels, vec!["202208/20220805p01.est", "202208/20220806p01.est", "202208/20220805p02.est"]);
+	assert_eq!(named[0].1, "@title=東京へ\n@cdate=2022-08-05\n\n東京駅は\n    コード\n");
+
+	// 同じ日を2つの元ファイルから作ろうとしたら、後の方は書かない
+	let mut owners = HashMap::from([(String::from("202208/20220805p01.est"), String::from("/diary/ChangeLog"))]);
+	assert!(claim(&mut owners, "202208/20220805p01.est", "/diary/ChangeLog").is_ok());
+	assert!(claim(&mut owners, "202208/20220806p01.est", "/diary/memo.md").is_ok());
+	assert_eq!(claim(&mut owners, "202208/20220805p01.est", "/diary/memo.md"), Err(String::from("/diary/ChangeLog")));
+	assert_eq!(owners["202208/20220806p01.est"], "/diary/memo.md");
+
+	let args: Vec<String> = ["--out", "/tmp/split", "-f", "changelog", "ChangeLog"].iter().map(|s| s.to_string()).collect();
+	let opts = parse_args(&args).unwrap();
+	assert_eq!(opts.out, "/tmp/split");
+	assert_eq!(opts.format, Some(Format::ChangeLog));
+	assert_eq!(opts.files, vec![String::from("ChangeLog")]);
+	assert!(parse_args(&args[..2]).is_err());
+    }
+}
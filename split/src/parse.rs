@@ -0,0 +1,187 @@
+use regex::Regex;
+
+// 1日の中の1件。YYYYMMDDpNN の NN はこの並び順で振る。
+#[derive(Debug, PartialEq)]
+pub struct Part {
+    pub date: u32,	// YYYYMMDD
+    pub title: String,
+    pub body: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    ChangeLog,	// ChangeLog メモ (chalow)
+    Headings,	// 日付の見出し
+}
+
+pub struct Parser {
+    changelog_date: Regex,
+    heading_date: Regex,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+	Parser {
+	    // 2022-08-05  masm  <masm@example.com>
+	    changelog_date: Regex::new(r"^(?P<y>\d{4})-(?P<m>\d{2})-(?P<d>\d{2})(?:\s|$)").unwrap(),
+	    // # 2022-08-05 題名 / * 2022/8/5 / 2022-08-05 (金)
+	    heading_date: Regex::new(r"^(?:[#*]+\s*)?(?P<y>\d{4})[-/](?P<m>\d{1,2})[-/](?P<d>\d{1,2})(?:\s*\(.\))?(?:\s+(?P<title>.*))?$").unwrap(),
+	}
+    }
+
+    // 日付行の後にタブ付きの "* " で始まる項目があれば ChangeLog とみなす
+    pub fn detect(&self, buf: &str) -> Format {
+	let mut after_date = false;
+	for line in buf.lines() {
+	    if self.changelog_date.is_match(line) {
+		after_date = true;
+	    } else if after_date && line.starts_with('\t') {
+		return if line.trim_start().starts_with("* ") || line.trim() == "*" {
+		    Format::ChangeLog
+		} else {
+		    Format::Headings
+		};
+	    }
+	}
+	Format::Headings
+    }
+
+    pub fn parse(&self, buf: &str, format: Format) -> Vec<Part> {
+	match format {
+	    Format::ChangeLog => self.changelog(buf),
+	    Format::Headings => self.headings(buf),
+	}
+    }
+
+    fn changelog(&self, buf: &str) -> Vec<Part> {
+	let mut parts = Vec::<Part>::new();
+	let mut date = None;
+	let mut cur: Option<(String, Vec<String>)> = None;
+	let flush = |parts: &mut Vec<Part>, date: Option<u32>, cur: &mut Option<(String, Vec<String>)>| {
+	    if let (Some(date), Some((title, lines))) = (date, cur.take()) {
+		parts.push(Self::make_part(date, title, lines));
+	    }
+	};
+	for line in buf.lines() {
+	    if let Some(caps) = self.changelog_date.captures(line) {
+		flush(&mut parts, date, &mut cur);
+		date = Self::to_date(&caps["y"], &caps["m"], &caps["d"]);
+		continue;
+	    }
+	    let text = match line.strip_prefix('\t') {
+		Some(text) => text,
+		None if line.trim().is_empty() => "",
+		None => line,
+	    };
+	    if let Some(item) = text.strip_prefix("* ").or(if text == "*" { Some("") } else { None }) {
+		flush(&mut parts, date, &mut cur);
+		// "* 題名 [分類]: 本文" の ": " より後ろは本文の1行目
+		let (title, first) = match item.split_once(": ") {
+		    Some((title, first)) => (title.to_string(), Some(first.to_string())),
+		    None => (item.trim_end_matches(':').to_string(), None),
+		};
+		cur = Some((title, first.into_iter().collect()));
+		continue;
+	    }
+	    if let Some((_, lines)) = cur.as_mut() {
+		lines.push(text.to_string());
+	    }
+	}
+	flush(&mut parts, date, &mut cur);
+	parts
+    }
+
+    fn headings(&self, buf: &str) -> Vec<Part> {
+	let mut parts = Vec::<Part>::new();
+	let mut cur: Option<(u32, String, Vec<String>)> = None;
+	for line in buf.lines() {
+	    let heading = self.heading_date.captures(line).and_then(|caps| {
+		let date = Self::to_date(&caps["y"], &caps["m"], &caps["d"])?;
+		Some((date, caps.name("title").map_or("", |m| m.as_str()).trim().to_string()))
+	    });
+	    match heading {
+		Some((date, title)) => {
+		    if let Some((date, title, lines)) = cur.take() {
+			parts.push(Self::make_part(date, title, lines));
+		    }
+		    cur = Some((date, title, vec![]));
+		},
+		None => {
+		    if let Some((_, _, lines)) = cur.as_mut() {
+			lines.push(line.to_string());
+		    }
+		},
+	    }
+	}
+	if let Some((date, title, lines)) = cur.take() {
+	    parts.push(Self::make_part(date, title, lines));
+	}
+	parts
+    }
+
+    // 題名が無ければ本文の1行目。前後の空行は落とす。
+    fn make_part(date: u32, title: String, lines: Vec<String>) -> Part {
+	let body = lines.join("\n").trim_matches('\n').trim_end().to_string();
+	let mut title = title.trim().to_string();
+	if title.is_empty() {
+	    title = body.lines().map(|line| line.trim()).find(|line| !line.is_empty()).unwrap_or("").to_string();
+	}
+	Part { date, title, body }
+    }
+
+    fn to_date(y: &str, m: &str, d: &str) -> Option<u32> {
+	let (y, m, d): (u32, u32, u32) = (y.parse().ok()?, m.parse().ok()?, d.parse().ok()?);
+	if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+	    return None;
+	}
+	Some(y * 10000 + m * 100 + d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changelog() {
+	let buf = "2022-08-06  masm  <masm@example.com>\n\
+		   \n\
+		   \t* 雨 [天気]:\n\
+		   \t一日中降っていた。\n\
+		   \n\
+		   2022-08-05  masm  <masm@example.com>\n\
+		   \n\
+		   \t* 東京へ: 東京駅は混んでいた。\n\
+		   \t\tコード\n\
+		   \n\
+		   \t* 帰った\n\
+		   \t夜。\n";
+	let parser = Parser::new();
+	assert_eq!(parser.detect(buf), Format::ChangeLog);
+	let parts = parser.parse(buf, Format::ChangeLog);
+	assert_eq!(parts, vec![
+	    Part { date: 20220806, title: String::from("雨 [天気]"), body: String::from("一日中降っていた。") },
+	    Part { date: 20220805, title: String::from("東京へ"), body: String::from("東京駅は混んでいた。\n\tコード") },
+	    Part { date: 20220805, title: String::from("帰った"), body: String::from("夜。") },
+	]);
+    }
+
+    #[test]
+    fn headings() {
+	let buf = "# 2022年8月\n\
+		   \n\
+		   ## 2022-08-05 東京へ\n\
+		   東京駅は混んでいた。\n\
+		   \n\
+		   ## 2022/8/6 (土)\n\
+		   雨。\n\
+		   2022-08-07に続く。\n";
+	let parser = Parser::new();
+	assert_eq!(parser.detect(buf), Format::Headings);
+	let parts = parser.parse(buf, Format::Headings);
+	assert_eq!(parts, vec![
+	    Part { date: 20220805, title: String::from("東京へ"), body: String::from("東京駅は混んでいた。") },
+	    Part { date: 20220806, title: String::from("雨。"), body: String::from("雨。\n2022-08-07に続く。") },
+	]);
+    }
+}